@@ -5,22 +5,54 @@ use std::str;
 fn main() {
     println!("cargo:rerun-if-changed=build.rs");
 
-    let Some(minor) = rustc_minor_version() else {
+    let Some(rustc) = rustc_version() else {
         return;
     };
 
-    if minor >= 77 {
+    if rustc.minor >= 77 {
         println!("cargo:rustc-check-cfg=cfg(no_diagnostic_namespace)");
+        println!("cargo:rustc-check-cfg=cfg(quote_def_site)");
     }
 
     // Support for the `#[diagnostic]` tool attribute namespace
     // https://blog.rust-lang.org/2024/05/02/Rust-1.78.0.html#diagnostic-attributes
-    if minor < 78 {
+    if rustc.minor < 78 {
         println!("cargo:rustc-cfg=no_diagnostic_namespace");
     }
+
+    // Span::def_site is unstable, and only provided by proc-macro2 when built
+    // with `--cfg procmacro2_semver_exempt` in RUSTFLAGS. The flags are read
+    // from Cargo rather than from this script's own cfg, which does not
+    // include RUSTFLAGS when building with `--target`.
+    if rustc.nightly && semver_exempt() {
+        println!("cargo:rustc-cfg=quote_def_site");
+    }
+}
+
+fn semver_exempt() -> bool {
+    let Ok(flags) = env::var("CARGO_ENCODED_RUSTFLAGS") else {
+        return false;
+    };
+    let mut flags = flags.split('\x1f');
+    while let Some(flag) = flags.next() {
+        let cfg = match flag.strip_prefix("--cfg") {
+            Some("") => flags.next(),
+            Some(rest) => rest.strip_prefix('='),
+            None => None,
+        };
+        if cfg == Some("procmacro2_semver_exempt") {
+            return true;
+        }
+    }
+    false
+}
+
+struct RustcVersion {
+    minor: u32,
+    nightly: bool,
 }
 
-fn rustc_minor_version() -> Option<u32> {
+fn rustc_version() -> Option<RustcVersion> {
     let rustc = env::var_os("RUSTC")?;
     let output = Command::new(rustc).arg("--version").output().ok()?;
     let version = str::from_utf8(&output.stdout).ok()?;
@@ -28,5 +60,7 @@ fn rustc_minor_version() -> Option<u32> {
     if pieces.next() != Some("rustc 1") {
         return None;
     }
-    pieces.next()?.parse().ok()
+    let minor = pieces.next()?.parse().ok()?;
+    let nightly = version.contains("nightly") || version.contains("-dev");
+    Some(RustcVersion { minor, nightly })
 }
@@ -110,14 +110,71 @@
 #[macro_export]
 macro_rules! format_ident {
     ($fmt:expr) => {
-        $crate::format_ident_impl!([
+        $crate::format_ident_impl!(mk_ident [
             $crate::__private::Option::None,
             $fmt
         ])
     };
 
     ($fmt:expr, $($rest:tt)*) => {
-        $crate::format_ident_impl!([
+        $crate::format_ident_impl!(mk_ident [
+            $crate::__private::Option::None,
+            $fmt
+        ] $($rest)*)
+    };
+}
+
+/// Formatting macro for constructing string `Literal`s.
+///
+/// This takes the same arguments as [`format_ident!`], formatting them with
+/// [`IdentFragment`] by default, but produces a string literal instead of an
+/// identifier, so the result may contain any text. It saves going through
+/// `format!` and [`Literal::string`], which would leave the literal with the
+/// span of the call site.
+///
+/// [`IdentFragment`]: crate::IdentFragment
+/// [`Literal::string`]: proc_macro2::Literal::string
+///
+/// <br>
+///
+/// # Hygiene
+///
+/// The [`Span`] of the first `Ident` argument is used as the span of the
+/// literal, falling back to [`Span::call_site`] when no identifiers are
+/// provided. Like with `format_ident!`, the span can be overridden by passing
+/// the `span` named argument.
+///
+/// [`Span`]: proc_macro2::Span
+/// [`Span::call_site`]: proc_macro2::Span::call_site
+///
+/// <br>
+///
+/// # Example
+///
+/// ```
+/// # use quote::{format_ident, quote, quote_lit};
+/// let name = format_ident!("r#type");
+/// let message = quote_lit!("field `{}` is missing", name);
+///
+/// let tokens = quote! {
+///     return Err(Error::new(#message));
+/// };
+/// assert_eq!(
+///     tokens.to_string(),
+///     "return Err (Error :: new (\"field `type` is missing\")) ;",
+/// );
+/// ```
+#[macro_export]
+macro_rules! quote_lit {
+    ($fmt:expr) => {
+        $crate::format_ident_impl!(mk_lit [
+            $crate::__private::Option::None,
+            $fmt
+        ])
+    };
+
+    ($fmt:expr, $($rest:tt)*) => {
+        $crate::format_ident_impl!(mk_lit [
             $crate::__private::Option::None,
             $fmt
         ] $($rest)*)
@@ -127,42 +184,42 @@ macro_rules! format_ident {
 #[macro_export]
 #[doc(hidden)]
 macro_rules! format_ident_impl {
-    // Final state
-    ([$span:expr, $($fmt:tt)*]) => {
-        $crate::__private::mk_ident(
+    // Final state, in which $mk is `mk_ident` or `mk_lit`
+    ($mk:ident [$span:expr, $($fmt:tt)*]) => {
+        $crate::__private::$mk(
             &$crate::__private::format!($($fmt)*),
             $span,
         )
     };
 
     // Span argument
-    ([$old:expr, $($fmt:tt)*] span = $span:expr) => {
-        $crate::format_ident_impl!([$old, $($fmt)*] span = $span,)
+    ($mk:ident [$old:expr, $($fmt:tt)*] span = $span:expr) => {
+        $crate::format_ident_impl!($mk [$old, $($fmt)*] span = $span,)
     };
-    ([$old:expr, $($fmt:tt)*] span = $span:expr, $($rest:tt)*) => {
-        $crate::format_ident_impl!([
+    ($mk:ident [$old:expr, $($fmt:tt)*] span = $span:expr, $($rest:tt)*) => {
+        $crate::format_ident_impl!($mk [
             $crate::__private::Option::Some::<$crate::__private::Span>($span),
             $($fmt)*
         ] $($rest)*)
     };
 
     // Named argument
-    ([$span:expr, $($fmt:tt)*] $name:ident = $arg:expr) => {
-        $crate::format_ident_impl!([$span, $($fmt)*] $name = $arg,)
+    ($mk:ident [$span:expr, $($fmt:tt)*] $name:ident = $arg:expr) => {
+        $crate::format_ident_impl!($mk [$span, $($fmt)*] $name = $arg,)
     };
-    ([$span:expr, $($fmt:tt)*] $name:ident = $arg:expr, $($rest:tt)*) => {
+    ($mk:ident [$span:expr, $($fmt:tt)*] $name:ident = $arg:expr, $($rest:tt)*) => {
         match $crate::__private::IdentFragmentAdapter(&$arg) {
-            arg => $crate::format_ident_impl!([$span.or(arg.span()), $($fmt)*, $name = arg] $($rest)*),
+            arg => $crate::format_ident_impl!($mk [$span.or(arg.span()), $($fmt)*, $name = arg] $($rest)*),
         }
     };
 
     // Positional argument
-    ([$span:expr, $($fmt:tt)*] $arg:expr) => {
-        $crate::format_ident_impl!([$span, $($fmt)*] $arg,)
+    ($mk:ident [$span:expr, $($fmt:tt)*] $arg:expr) => {
+        $crate::format_ident_impl!($mk [$span, $($fmt)*] $arg,)
     };
-    ([$span:expr, $($fmt:tt)*] $arg:expr, $($rest:tt)*) => {
+    ($mk:ident [$span:expr, $($fmt:tt)*] $arg:expr, $($rest:tt)*) => {
         match $crate::__private::IdentFragmentAdapter(&$arg) {
-            arg => $crate::format_ident_impl!([$span.or(arg.span()), $($fmt)*, arg] $($rest)*),
+            arg => $crate::format_ident_impl!($mk [$span.or(arg.span()), $($fmt)*, arg] $($rest)*),
         }
     };
 }
@@ -103,6 +103,7 @@
 )]
 
 extern crate alloc;
+#[cfg(feature = "std")]
 extern crate std;
 
 #[cfg(feature = "proc-macro")]
@@ -111,6 +112,7 @@ extern crate proc_macro;
 mod ext;
 mod format;
 mod ident_fragment;
+mod repeat;
 mod to_tokens;
 
 // Not public API.
@@ -120,6 +122,9 @@ pub mod __private;
 
 pub use crate::ext::TokenStreamExt;
 pub use crate::ident_fragment::IdentFragment;
+pub use crate::repeat::QuoteRepeat;
+#[cfg(feature = "std")]
+pub use crate::repeat::Sorted;
 pub use crate::to_tokens::ToTokens;
 
 // Not public API.
@@ -153,13 +158,226 @@ macro_rules! __quote {
         /// `macro_rules!`. This iterates through the elements of any variable
         /// interpolated within the repetition and inserts a copy of the repetition body
         /// for each one. The variables in an interpolation may be a `Vec`, slice,
-        /// `BTreeSet`, or any `Iterator`.
+        /// array, `VecDeque`, `LinkedList`, `BinaryHeap`, `BTreeSet`, or any
+        /// `Iterator`. A `BTreeMap` is repeated over its values in the order of its
+        /// keys. With the default `std` feature, `HashSet` and `HashMap` (values)
+        /// are supported too; wrap them in [`Sorted`] for an order that does not
+        /// change between builds. Other collections can take part by implementing
+        /// [`QuoteRepeat`]. An `Option` is not iterated by `#(...)*`, since
+        /// it interpolates as its contents, but controls `#(...)?` as described
+        /// below.
         ///
         /// - `#(#var)*` — no separators
         /// - `#(#var),*` — the character before the asterisk is used as a separator
         /// - `#( struct #var; )*` — the repetition can contain other tokens
         /// - `#( #k => println!("{}", #v), )*` — even multiple interpolations
         ///
+        /// When several iterators are interpolated in the same repetition, it stops
+        /// at the end of the shortest one. Enabling this crate's `checked` feature
+        /// turns a difference in length into a panic naming each of the variables
+        /// and its length, which is useful for catching dropped elements in
+        /// development. An iterator that does not know its exact size, such as
+        /// an unbounded one, is only advanced one element past the end of the
+        /// repetition and is reported with a lower bound on its length.
+        ///
+        /// Repetitions written with `+` instead of `*`, as in `#(#var)+` or
+        /// `#(#var),+`, behave the same but panic if the iterators interpolated
+        /// inside of them produce no elements, for constructs that would otherwise
+        /// expand to invalid code such as a `where` clause with no predicates.
+        ///
+        /// A separator of more than one token is written inside of brackets after a
+        /// `#`, as in `#(#arms)#[=> (),]*`, and may itself contain interpolations.
+        /// A bracketed group without the `#`, as in `#(#var)[0]*`, is a separator
+        /// of one token like any other. A separator
+        /// held in a variable, for example `+` for trait bounds versus `|` for
+        /// patterns, is interpolated directly as `#(#var)#sep*`.
+        ///
+        /// Writing `?` between the separator and the `*`, as in `#(#var),?*` or
+        /// `#(#var)#[=> (),]?*`, additionally emits the separator after the last
+        /// element, but only if there was at least one element. This suits struct
+        /// literals, match arms and generic parameter lists whose contents may be
+        /// empty. A separator that should always be present is written as an
+        /// ordinary token after the repetition, as in `#(#var),*,`. A `?` after the
+        /// `*`, as in `#(#var).*?`, is an ordinary token following the repetition.
+        ///
+        /// Elements that do not themselves implement `ToTokens`, such as tuples, are
+        /// repeated over by naming their parts with a pattern, as in
+        /// `#(for (name, ty) in pairs) { #name: #ty },*`. The braces hold the body
+        /// of the repetition and are followed by an optional separator and a
+        /// mandatory `*`. The expression after `in` may be anything that can be
+        /// interpolated in a repetition, including `#var` for the current element
+        /// of an enclosing repetition.
+        ///
+        /// A repetition whose body begins with `#@name` binds `name` to the zero-based
+        /// index of the current iteration, as in `#( #@i #names: self.#i ),*`. The
+        /// index interpolates as an unsuffixed integer literal, dereferences to `usize`
+        /// for arithmetic such as `#{*#i + 1}`, and can be passed to [`format_ident!`]
+        /// as in `#{format_ident!("_{}", #i)}`.
+        /// Each level of nested repetition names its own index.
+        ///
+        /// An optional repetition `#(...)?` is emitted once if the `Option` values
+        /// interpolated within it are all `Some`, and omitted otherwise. Inside the
+        /// repetition those variables refer to the value contained in the `Some`. For
+        /// example `#(where #clause)?` with `clause: Option<WhereClause>`. Only
+        /// `Option` can control an optional repetition; any other iterable
+        /// interpolated inside of one is a compile error.
+        ///
+        /// Arbitrary expressions are interpolated with `#{expr}`, for example
+        /// `#{self.name}` or `#{idx + 1}`. The expression is evaluated in the scope of
+        /// the macro invocation and may produce any type implementing `ToTokens`.
+        /// Inside of a repetition, the name of each variable interpolated by the
+        /// repetition refers to its current element, so `#( #name = #{name.len()} ),*`
+        /// calls `len` on each element of `name` in turn.
+        ///
+        /// Within the expression, `#var` counts as an interpolation of `var` the same
+        /// as it would outside of the braces, meaning a repetition iterates over it.
+        /// This allows projecting several pieces out of each element of a single
+        /// iterable without first splitting it into separate collections:
+        ///
+        /// - `#( #{#field.ident}: #{#field.ty} ),*` — fields of each element
+        /// - `#( #{#variant.name()} )*` — method calls on each element
+        ///
+        /// To emit a `#` followed by an identifier without interpolating it, as when
+        /// generating a `macro_rules!` body or another `quote!` invocation, write
+        /// the identifier in double brackets: `#[[ident]]` produces the tokens
+        /// `# ident`. Note that `# #ident` is a `#` followed by an interpolation.
+        ///
+        /// A doc comment is emitted as it is written, `#` signs and all, unless it
+        /// is wrapped in `#(doc ...)` together with the variables that its text
+        /// refers to, listed as `#var` before it. In the text of such a comment,
+        /// each `#var` is replaced by the value of the variable. An identifier is
+        /// written the way [`format_ident!`] writes it, without the `r#` of a raw
+        /// identifier. Any other value is written by its `Display` implementation,
+        /// or as its tokens if it has none. Inside of a repetition, the variable
+        /// refers to the current element.
+        ///
+        /// Any other `#name` in the text of the comment is a compile error, and
+        /// `##name` produces the text `#name`. A `#` right after a letter, digit
+        /// or underscore, as in `r#type`, is not the start of a name.
+        ///
+        /// The comments may be `///`, `/** */` or `#[doc = "..."]`, or the inner
+        /// `//!` or `/*! */`, but one `#(doc ...)` cannot mix outer and inner
+        /// comments. Since a `///` comment runs to the end of its line, the
+        /// closing parenthesis goes on a line of its own.
+        ///
+        /// ```
+        /// # use quote::{format_ident, quote};
+        /// #
+        /// # let name = format_ident!("Point");
+        /// # let fields = [format_ident!("x"), format_ident!("y")];
+        /// #
+        /// quote! {
+        ///     #(doc #name
+        ///         /// Accessors for the fields of #name.
+        ///     )
+        ///     impl #name {
+        ///         #(
+        ///             #(doc #fields
+        ///                 /// Returns the #fields field.
+        ///             )
+        ///             pub fn #fields(&self) -> i32 {
+        ///                 self.#fields
+        ///             }
+        ///         )*
+        ///     }
+        /// }
+        /// # ;
+        /// ```
+        ///
+        /// A value used in several places can be computed within the template by
+        /// `#(let name = expr;)`, as in
+        /// `#(let builder = format_ident!("{}Builder", #name);)`. The binding is
+        /// visible to every later interpolation in the same group, including
+        /// those inside of repetitions and nested groups. A binding written inside
+        /// of a repetition is computed once per iteration, and `#var` in the
+        /// expression refers to the current element as it does in `#{...}`. The
+        /// semicolon is required, and like any `#(...)`, a binding followed by `*`
+        /// or by a single token and `*` is taken to be a repetition instead.
+        /// Inside of a repetition, a binding may not reuse the name of a variable
+        /// from outside of it that the repetition also interpolates, which is a
+        /// compile error; give the binding a different name instead.
+        ///
+        /// A fragment that is easier to produce imperatively can be written as a
+        /// closure, `#(|tokens| { ... })`, which is called with the `&mut
+        /// TokenStream` under construction at that point so that it may append to
+        /// it using [`TokenStreamExt`] or [`ToTokens::to_tokens`]. The closure may
+        /// refer to variables of an enclosing repetition by name, without `#`, to
+        /// get the current element.
+        /// The parameter may be any pattern, or an identifier with a type, and the
+        /// closure may be a `move` closure.
+        ///
+        /// ```
+        /// # use quote::{quote, TokenStreamExt};
+        /// # use proc_macro2::{Literal, Punct, Spacing};
+        /// #
+        /// let n = 4usize;
+        /// let tokens = quote! {
+        ///     const POWERS: [u32; #n] = [#(|tokens| {
+        ///         let mut power = 1u32;
+        ///         for _ in 0..n {
+        ///             tokens.append(Literal::u32_unsuffixed(power));
+        ///             tokens.append(Punct::new(',', Spacing::Alone));
+        ///             power *= 2;
+        ///         }
+        ///     })];
+        /// };
+        /// # assert_eq!(tokens.to_string(), "const POWERS : [u32 ; 4usize] = [1 , 2 , 4 , 8 ,] ;");
+        /// ```
+        ///
+        /// Conditional output is done using `#(if cond) { ... }`, optionally
+        /// followed by `#(else) { ... }`. The condition is an arbitrary `bool`
+        /// expression evaluated when the tokens are built, and the contents of the
+        /// braces are quoted as if they had appeared in place of the conditional
+        /// (the braces themselves are not part of the output). Inside of a
+        /// repetition, the condition can test the current element of `var` by
+        /// writing `#(if *#var)`.
+        ///
+        /// ```
+        /// # use quote::quote;
+        /// #
+        /// # let name = quote!(Demo);
+        /// # let is_unit = false;
+        /// #
+        /// quote! {
+        ///     #(if is_unit) {
+        ///         struct #name;
+        ///     } #(else) {
+        ///         struct #name {}
+        ///     }
+        /// }
+        /// # ;
+        /// ```
+        ///
+        /// Branching on an enum is done using `#(match expr) { ... }`, in which every
+        /// arm is written as `Pattern => { ... }` with an optional `if` guard. The
+        /// names bound by the pattern are available for interpolation in the body of
+        /// the arm, and as with a conditional the braces around the body are not part
+        /// of the output. Inside of a repetition, the current element of `var` is
+        /// matched by writing `#(match *#var)`.
+        ///
+        /// ```
+        /// # use quote::quote;
+        /// # use proc_macro2::Ident;
+        /// #
+        /// enum Fields {
+        ///     Named(Vec<Ident>),
+        ///     Unnamed(usize),
+        ///     Unit,
+        /// }
+        /// #
+        /// # let name = quote!(Demo);
+        /// # let fields = Fields::Unnamed(1);
+        ///
+        /// quote! {
+        ///     struct #name #(match fields) {
+        ///         Fields::Named(names) => { { #(#names: u8,)* } }
+        ///         Fields::Unnamed(len) => { (#(for _ in 0..len) { u8 },*); }
+        ///         Fields::Unit => { ; }
+        ///     }
+        /// }
+        /// # ;
+        /// ```
+        ///
         /// <br>
         ///
         /// # Hygiene
@@ -172,6 +390,31 @@ macro_rules! __quote {
         ///
         /// A different span can be provided through the [`quote_spanned!`] macro.
         ///
+        /// A span for just part of the invocation is given by writing that part as
+        /// `#(span = expr) { ... }`, where `expr` is anything accepted as the span
+        /// of `quote_spanned!`. The tokens inside of the braces are spanned as if
+        /// they had been written in `quote_spanned!`, and the braces themselves are
+        /// not part of the output. Regions may be nested, and the tokens after one
+        /// go back to the span in effect before it.
+        ///
+        /// ```
+        /// # use quote::quote;
+        /// # use proc_macro2::Span;
+        /// #
+        /// # let name = quote!(Demo);
+        /// # let ty = quote!(T);
+        /// # let ty_span = Span::call_site();
+        /// #
+        /// quote! {
+        ///     impl #name
+        ///     #(span = ty_span) {
+        ///         where #ty: Sync
+        ///     }
+        ///     {}
+        /// }
+        /// # ;
+        /// ```
+        ///
         /// <br>
         ///
         /// # Return type
@@ -304,6 +547,32 @@ macro_rules! __quote {
         /// # ;
         /// ```
         ///
+        /// The same identifier can be built inline by listing its fragments inside
+        /// of `#(ident ...)`. Each fragment is an identifier or integer literal
+        /// written as is, or an interpolation `#var` or `#{expr}` of any
+        /// [`IdentFragment`]. The span is chosen the same way as by
+        /// `format_ident!`, from the first interpolated fragment that has one,
+        /// falling back to the call site, or to the given span in
+        /// `quote_spanned!`. Inside of a repetition, `#var`
+        /// refers to the current element, which keeps accessor generation short:
+        ///
+        /// ```
+        /// # use quote::{format_ident, quote};
+        /// #
+        /// # let fields = [format_ident!("x"), format_ident!("y")];
+        /// #
+        /// quote! {
+        ///     #(
+        ///         pub fn #(ident get_ #fields)(&self) -> i32 { self.#fields }
+        ///         pub fn #(ident set_ #fields)(&mut self, v: i32) { self.#fields = v; }
+        ///     )*
+        /// }
+        /// # ;
+        /// ```
+        ///
+        /// Like `#(let ...)`, an `#(ident ...)` directly followed by `*`, or by a
+        /// single token and `*`, is a repetition instead.
+        ///
         /// Alternatively, the APIs provided by Syn and proc-macro2 can be used to
         /// directly build the identifier. This is roughly equivalent to the above, but
         /// will not handle `ident` being a raw identifier.
@@ -378,16 +647,9 @@ macro_rules! __quote {
         ///
         /// ### Interpolating text inside of doc comments
         ///
-        /// Neither doc comments nor string literals get interpolation behavior in
-        /// quote:
-        ///
-        /// ```compile_fail
-        /// quote! {
-        ///     /// try to interpolate: #ident
-        ///     ///
-        ///     /// ...
-        /// }
-        /// ```
+        /// A doc comment refers to variables only inside of `#(doc ...)`, as
+        /// described under *Interpolation* above. String literals do not get
+        /// interpolation behavior in quote:
         ///
         /// ```compile_fail
         /// quote! {
@@ -395,8 +657,9 @@ macro_rules! __quote {
         /// }
         /// ```
         ///
-        /// Instead the best way to build doc comments that involve variables is by
-        /// formatting the doc string literal outside of quote.
+        /// Instead the best way to build doc comments that involve more than the
+        /// `Display` representation of a variable is by formatting the doc string
+        /// literal outside of quote.
         ///
         /// ```rust
         /// # use proc_macro2::{Ident, Span};
@@ -509,6 +772,37 @@ __quote![
             $crate::ToTokens::to_tokens(&$var, &mut _s);
             _s
         }};
+        (# { $($expr:tt)* }) => {{
+            let mut _s = $crate::__private::TokenStream::new();
+            $crate::ToTokens::to_tokens(&($crate::quote_expr!{[] [] $($expr)*}), &mut _s);
+            _s
+        }};
+        (# (| $($closure:tt)*)) => {{
+            let mut _s = $crate::__private::TokenStream::new();
+            $crate::quote_each_token!{_s # (| $($closure)*)}
+            _s
+        }};
+        (# (move | $($closure:tt)*)) => {{
+            let mut _s = $crate::__private::TokenStream::new();
+            $crate::quote_each_token!{_s # (move | $($closure)*)}
+            _s
+        }};
+        (# [[$var:ident]]) => {{
+            let mut _s = $crate::__private::TokenStream::new();
+            $crate::__private::push_pound(&mut _s);
+            $crate::quote_token!{$var _s}
+            _s
+        }};
+        (# (doc $($docs:tt)*)) => {{
+            let mut _s = $crate::__private::TokenStream::new();
+            $crate::quote_each_token!{_s # (doc $($docs)*)}
+            _s
+        }};
+        (# (ident $($frags:tt)*)) => {{
+            let mut _s = $crate::__private::TokenStream::new();
+            $crate::quote_each_token!{_s # (ident $($frags)*)}
+            _s
+        }};
         ($tt1:tt $tt2:tt) => {{
             let mut _s = $crate::__private::TokenStream::new();
             $crate::quote_token!{$tt1 _s}
@@ -650,6 +944,37 @@ macro_rules! quote_spanned_with_expanded_span {
         $crate::ToTokens::to_tokens(&$var, &mut _s);
         _s
     };
+    ($span:ident # { $($expr:tt)* }) => {
+        let mut _s = $crate::__private::TokenStream::new();
+        $crate::ToTokens::to_tokens(&($crate::quote_expr!{[] [] $($expr)*}), &mut _s);
+        _s
+    };
+    ($span:ident # (| $($closure:tt)*)) => {
+        let mut _s = $crate::__private::TokenStream::new();
+        $crate::quote_each_token_spanned!{_s $span # (| $($closure)*)}
+        _s
+    };
+    ($span:ident # (move | $($closure:tt)*)) => {
+        let mut _s = $crate::__private::TokenStream::new();
+        $crate::quote_each_token_spanned!{_s $span # (move | $($closure)*)}
+        _s
+    };
+    ($span:ident # [[$var:ident]]) => {
+        let mut _s = $crate::__private::TokenStream::new();
+        $crate::__private::push_pound_spanned(&mut _s, $span);
+        $crate::quote_token_spanned!{$var _s $span}
+        _s
+    };
+    ($span:ident # (doc $($docs:tt)*)) => {
+        let mut _s = $crate::__private::TokenStream::new();
+        $crate::quote_each_token_spanned!{_s $span # (doc $($docs)*)}
+        _s
+    };
+    ($span:ident # (ident $($frags:tt)*)) => {
+        let mut _s = $crate::__private::TokenStream::new();
+        $crate::quote_each_token_spanned!{_s $span # (ident $($frags)*)}
+        _s
+    };
     ($span:ident $tt1:tt $tt2:tt) => {
         let mut _s = $crate::__private::TokenStream::new();
         $crate::quote_token_spanned!{$tt1 _s $span}
@@ -665,19 +990,215 @@ macro_rules! quote_spanned_with_expanded_span {
     };
 }
 
-// Extract the names of all #metavariables and pass them to the $call macro.
+/// Same as `quote!`, but appends the tokens to an existing `TokenStream`
+/// instead of returning a new one.
+///
+/// <br>
+///
+/// # Syntax
+///
+/// An expression of type `&mut TokenStream`, followed by `=>`, followed by the
+/// tokens to quote, which are written exactly as they would be inside of
+/// `quote!`.
+///
+/// Building a large output out of many small `quote!` invocations allocates a
+/// `TokenStream` for each of them, only for it to be copied into the one being
+/// accumulated. `quote_into!` writes each token directly to its destination,
+/// which is the natural fit inside of a [`ToTokens::to_tokens`]
+/// implementation.
+///
+/// ```
+/// # use proc_macro2::{Ident, TokenStream};
+/// # use quote::{quote_into, ToTokens};
+/// #
+/// struct Field {
+///     name: Ident,
+///     ty: TokenStream,
+/// }
+///
+/// impl ToTokens for Field {
+///     fn to_tokens(&self, tokens: &mut TokenStream) {
+///         let name = &self.name;
+///         let ty = &self.ty;
+///         quote_into!(tokens => pub #name: #ty,);
+///     }
+/// }
+/// #
+/// # let field = Field {
+/// #     name: quote::format_ident!("x"),
+/// #     ty: quote::quote!(i32),
+/// # };
+/// # let mut tokens = TokenStream::new();
+/// # quote_into!(&mut tokens => struct Point { #field });
+/// # assert_eq!(tokens.to_string(), "struct Point { pub x : i32 , }");
+/// ```
+#[macro_export]
+macro_rules! quote_into {
+    ($tokens:expr => $($tt:tt)*) => {{
+        #[allow(unused_mut)]
+        let mut _s: &mut $crate::__private::TokenStream = $tokens;
+        $crate::quote_each_token!{_s $($tt)*}
+    }};
+}
+
+/// Same as `quote_spanned!`, but appends the tokens to an existing
+/// `TokenStream` instead of returning a new one.
+///
+/// The destination, an expression of type `&mut TokenStream`, comes first and
+/// is separated from the span by a comma, as in
+/// `quote_spanned_into!(tokens, span=> ...)`. See [`quote_into!`] for why this
+/// is preferable to extending the destination by the result of
+/// `quote_spanned!`.
+///
+/// ```
+/// # use proc_macro2::{Span, TokenStream};
+/// # use quote::quote_spanned_into;
+/// #
+/// # let span = Span::call_site();
+/// # let init = 0;
+/// #
+/// let mut tokens = TokenStream::new();
+/// quote_spanned_into!(&mut tokens, span=> Box::into_raw(Box::new(#init)));
+/// ```
+#[macro_export]
+macro_rules! quote_spanned_into {
+    ($tokens:expr, $span:expr=> $($tt:tt)*) => {{
+        #[allow(unused_mut)]
+        let mut _s: &mut $crate::__private::TokenStream = $tokens;
+        let _span: $crate::__private::Span = $crate::__private::get_span($span).__into_span();
+        $crate::quote_each_token_spanned!{_s _span $($tt)*}
+    }};
+}
+
+/// Same as `quote!`, but produces a value implementing [`ToTokens`] that quotes
+/// the tokens when it is interpolated, rather than a `TokenStream`.
+///
+/// A fragment that is built only to be interpolated once into a larger
+/// template is written straight into the outer `TokenStream`, without
+/// allocating a `TokenStream` of its own and copying it over. The variables
+/// interpolated by the fragment are captured by value, like in a `move`
+/// closure, so borrow anything that is still needed afterward.
+///
+/// ```
+/// # use proc_macro2::{Ident, TokenStream};
+/// # use quote::{format_ident, quote, quote_lazy, ToTokens};
+/// #
+/// struct Field {
+///     name: Ident,
+///     ty: TokenStream,
+/// }
+///
+/// impl Field {
+///     fn decl(&self) -> impl ToTokens + '_ {
+///         let name = &self.name;
+///         let ty = &self.ty;
+///         quote_lazy!(pub #name: #ty,)
+///     }
+/// }
+///
+/// let fields = [
+///     Field { name: format_ident!("x"), ty: quote!(i32) },
+///     Field { name: format_ident!("y"), ty: quote!(i32) },
+/// ];
+/// let decls = fields.iter().map(Field::decl);
+///
+/// let tokens = quote! {
+///     struct Point {
+///         #(#decls)*
+///     }
+/// };
+/// # assert_eq!(tokens.to_string(), "struct Point { pub x : i32 , pub y : i32 , }");
+/// ```
+///
+/// The tokens are quoted again every time the value is interpolated, so any
+/// expression inside of them, such as in `#{...}` or `#(if ...)`, is evaluated
+/// each time, and a variable repeated over by `#(...)*` must be a collection
+/// rather than an iterator, which could only be consumed once.
+#[macro_export]
+macro_rules! quote_lazy {
+    ($($tt:tt)*) => {
+        $crate::__private::Lazy(move |_tokens: &mut $crate::__private::TokenStream| {
+            $crate::quote_into!(_tokens => $($tt)*);
+        })
+    };
+}
+
+/// Same as `quote!`, but applies [`Span::mixed_site()`] to all tokens
+/// originating within the macro invocation.
+///
+/// This is shorthand for `quote_spanned!(Span::mixed_site()=> ...)`. Local
+/// variables, labels and `$crate` in the output are resolved at the definition
+/// site of the procedural macro, the same hygiene as for `macro_rules!`, so
+/// that a variable introduced by the generated code cannot collide with one of
+/// the same name written by the user. Everything else, such as the names of
+/// items, types and methods, is resolved at the call site. Interpolated tokens
+/// keep their own spans.
+///
+/// [`Span::mixed_site()`]: proc_macro2::Span::mixed_site
+///
+/// ```
+/// # use quote::{quote, quote_mixed};
+/// #
+/// # let expr = quote!(f());
+/// #
+/// // `value` does not capture nor shadow a `value` in the user's `#expr`.
+/// let tokens = quote_mixed! {
+///     match #expr {
+///         value => Some(value),
+///     }
+/// };
+/// ```
+#[macro_export]
+macro_rules! quote_mixed {
+    ($($tt:tt)*) => {{
+        let _span: $crate::__private::Span = $crate::__private::Span::mixed_site();
+        $crate::quote_spanned_with_expanded_span!{_span $($tt)*}
+    }};
+}
+
+/// Same as `quote!`, but applies `Span::def_site()` to all tokens originating
+/// within the macro invocation.
+///
+/// Every name in the output is resolved at the definition site of the
+/// procedural macro, so the generated code can refer only to what the
+/// procedural macro crate itself can see. Interpolated tokens keep their own
+/// spans.
+///
+/// Definition-site hygiene is unstable. This macro is available only on a
+/// nightly compiler with `--cfg procmacro2_semver_exempt` in RUSTFLAGS, which
+/// is what makes `proc_macro2::Span::def_site()` available.
+#[cfg(quote_def_site)]
+#[macro_export]
+macro_rules! quote_def {
+    ($($tt:tt)*) => {{
+        let _span: $crate::__private::Span = $crate::__private::Span::def_site();
+        $crate::quote_spanned_with_expanded_span!{_span $($tt)*}
+    }};
+}
+
+// Extract the names of all #metavariables and pass them to the $call macro,
+// except for the names in brackets, which are bound inside of the repetition
+// being expanded rather than iterated by it. The names bound by a nested
+// `#(let pat = expr;)` are declared as placeholders for the tokens after it,
+// which refer to the value bound inside of the repetition instead.
 //
-// in:   pounded_var_names!(then!(...) a #b c #( #d )* #e)
+// in:   pounded_var_names!(then!(...) [a] a #b c #( #d )* #e #a #(let f = #g;) #f)
 // out:  then!(... b);
 //       then!(... d);
 //       then!(... e);
+//       then!(... g);
+//       let f = RepBinding;
+//       then!(... f);
 #[macro_export]
 #[doc(hidden)]
 macro_rules! pounded_var_names {
-    ($call:ident! $extra:tt $($tts:tt)*) => {
-        $crate::pounded_var_names_with_context!{$call! $extra
-            (@ $($tts)*)
-            ($($tts)* @)
+    ($call:ident! $extra:tt $excl:tt $($tts:tt)*) => {
+        $crate::pounded_var_names_with_context!{$call! $extra $excl
+            (@ @ $($tts)*)
+            (@ $($tts)* @)
+            ($($tts)* @ @)
+            ($($tts)* @ @ @)
+            ($($tts)* @ @ @ @)
         }
     };
 }
@@ -685,9 +1206,9 @@ macro_rules! pounded_var_names {
 #[macro_export]
 #[doc(hidden)]
 macro_rules! pounded_var_names_with_context {
-    ($call:ident! $extra:tt ($($b1:tt)*) ($($curr:tt)*)) => {
+    ($call:ident! $extra:tt $excl:tt ($($b2:tt)*) ($($b1:tt)*) ($($curr:tt)*) ($x:tt $($a1:tt)*) ($y:tt $z:tt $($a2:tt)*)) => {
         $(
-            $crate::pounded_var_with_context!{$call! $extra $b1 $curr}
+            $crate::pounded_var_with_context!{$call! $extra $excl $b2 $b1 $curr $a1 $a2}
         )*
     };
 }
@@ -695,23 +1216,226 @@ macro_rules! pounded_var_names_with_context {
 #[macro_export]
 #[doc(hidden)]
 macro_rules! pounded_var_with_context {
-    ($call:ident! $extra:tt $b1:tt ( $($inner:tt)* )) => {
-        $crate::pounded_var_names!{$call! $extra $($inner)*}
+    // A nested repetition that happens to start with `let`, as in
+    // `#(let #var = 0;)*`, which is scanned like any other group below.
+    ($call:ident! $extra:tt $excl:tt $b2:tt # (let $($stmt:tt)*) * $a2:tt) => {
+        $crate::pounded_var_names!{$call! $extra $excl let $($stmt)*}
+    };
+    ($call:ident! $extra:tt $excl:tt $b2:tt # (let $($stmt:tt)*) ? $a2:tt) => {
+        $crate::pounded_var_names!{$call! $extra $excl let $($stmt)*}
+    };
+    ($call:ident! $extra:tt $excl:tt $b2:tt # (let $($stmt:tt)*) + $a2:tt) => {
+        $crate::pounded_var_names!{$call! $extra $excl let $($stmt)*}
+    };
+    ($call:ident! $extra:tt $excl:tt $b2:tt # (let $($stmt:tt)*) $sep:tt *) => {
+        $crate::pounded_var_names!{$call! $extra $excl let $($stmt)*}
+    };
+    ($call:ident! $extra:tt $excl:tt $b2:tt # (let $($stmt:tt)*) $sep:tt +) => {
+        $crate::pounded_var_names!{$call! $extra $excl let $($stmt)*}
     };
 
-    ($call:ident! $extra:tt $b1:tt [ $($inner:tt)* ]) => {
-        $crate::pounded_var_names!{$call! $extra $($inner)*}
+    // A nested `#(let pat = expr;)`, whose expression may interpolate variables
+    // of this repetition, and whose names are declared as placeholders for the
+    // tokens after it.
+    ($call:ident! $extra:tt $excl:tt $b2:tt # (let $($stmt:tt)*) $a1:tt $a2:tt) => {
+        $crate::pounded_var_names!{$call! $extra $excl $($stmt)*}
+        $crate::pattern_var_names!{@let [] (bound) quote_bind_let!() [] $($stmt)*}
     };
 
-    ($call:ident! $extra:tt $b1:tt { $($inner:tt)* }) => {
-        $crate::pounded_var_names!{$call! $extra $($inner)*}
+    // A nested repetition that binds its index as `#@name`, which is not a
+    // variable of this repetition inside of the nested one.
+    ($call:ident! $extra:tt [$($excl:ident)*] $b2:tt # ( # @ $index:ident $($inner:tt)* ) $a1:tt $a2:tt) => {
+        $crate::pounded_var_names!{$call! $extra [$($excl)* $index] $($inner)*}
     };
 
-    ($call:ident!($($extra:tt)*) # $var:ident) => {
+    // The body of a nested `#(for pat in expr)` or the arms of a nested
+    // `#(match expr)`, in which the names bound by patterns are likewise not in
+    // scope yet at this level.
+    ($call:ident! $extra:tt $excl:tt $b2:tt (for $($pat:tt)*) { $($inner:tt)* } $a1:tt $a2:tt) => {
+        $crate::pattern_var_names!{(for $($inner)*) $call! $extra $excl $($pat)*}
+    };
+
+    ($call:ident! $extra:tt [$($excl:ident)*] $b2:tt (match $($expr:tt)*) { $($arms:tt)* } $a1:tt $a2:tt) => {
+        $crate::pattern_var_names!{(match $($excl)*) $call! $extra [$($excl)*] $($arms)*}
+    };
+
+    ($call:ident! $extra:tt $excl:tt $b2:tt $b1:tt ( $($inner:tt)* ) $a1:tt $a2:tt) => {
+        $crate::pounded_var_names!{$call! $extra $excl $($inner)*}
+    };
+
+    ($call:ident! $extra:tt $excl:tt $b2:tt $b1:tt [ $($inner:tt)* ] $a1:tt $a2:tt) => {
+        $crate::pounded_var_names!{$call! $extra $excl $($inner)*}
+    };
+
+    ($call:ident! $extra:tt $excl:tt $b2:tt $b1:tt { $($inner:tt)* } $a1:tt $a2:tt) => {
+        $crate::pounded_var_names!{$call! $extra $excl $($inner)*}
+    };
+
+    ($call:ident!($($extra:tt)*) [] $b2:tt # $var:ident $a1:tt $a2:tt) => {
         $crate::$call!($($extra)* $var);
     };
 
-    ($call:ident! $extra:tt $b1:tt $curr:tt) => {};
+    ($call:ident! $extra:tt $excl:tt $b2:tt # $var:ident $a1:tt $a2:tt) => {
+        $crate::quote_var_filter!{$call! $extra $excl $var $}
+    };
+
+    ($call:ident! $extra:tt $excl:tt $b2:tt $b1:tt $curr:tt $a1:tt $a2:tt) => {};
+}
+
+// Pass $var on to $call unless it is one of the excluded names, by way of a
+// local macro with a rule for each of them. The macro is used right where it
+// is defined, so it does not matter which other definitions of the same name
+// are in scope. The variable and the extra arguments go through the input of
+// the local macro rather than its body, which would change their hygiene.
+#[macro_export]
+#[doc(hidden)]
+macro_rules! quote_var_filter {
+    ($call:ident! $extra:tt [$($excl:ident)*] $var:ident $d:tt) => {
+        macro_rules! __quote_var_filter {
+            $(
+                ($d extra:tt $excl) => {};
+            )*
+            (($d($d extra:tt)*) $d var:ident) => {
+                $crate::$call!($d($d extra)* $d var);
+            };
+        }
+        __quote_var_filter!($extra $var);
+    };
+}
+
+// Collect the names bound by the pattern of a `#(for pat in expr)`, which is
+// all of the tokens up to `in`, and pass the tokens of its body on to
+// pounded_var_names with those names excluded. Likewise for the body of each
+// arm of a `#(match expr)`, excluding the names bound by the pattern of that
+// arm only. With `(bound)`, the names bound by the pattern of a
+// `#(let pat = expr;)`, which is the tokens up to `=` or `:` following `@let`,
+// are passed to $call themselves.
+//
+// Identifiers that are part of a path, name a tuple or struct pattern, or name
+// a field are not bindings and are skipped. Other unit structs and variants
+// imported by name cannot be told apart from bindings and are not supported in
+// a pattern nested inside of an enclosing repetition.
+//
+// in:   pattern_var_names!((for #name: #ty) then!(...) [i] (name, ty) in #fields)
+// out:  pounded_var_names!(then!(...) [i name ty] #name: #ty)
+//
+// in:   pattern_var_names!((match) then!(...) [] Kind::Named(fields) if x => { #fields #y } _ => {})
+// out:  pounded_var_names!(then!(...) [fields] #fields #y)
+//       pounded_var_names!(then!(...) [])
+//
+// in:   pattern_var_names!(@let [] (bound) then!(...) [] (x, y) = #z;)
+// out:  then!(... x);
+//       then!(... y);
+#[macro_export]
+#[doc(hidden)]
+macro_rules! pattern_var_names {
+    ((for $($inner:tt)*) $call:ident! $extra:tt $excl:tt in $($rest:tt)*) => {
+        $crate::pounded_var_names!{$call! $extra $excl $($inner)*}
+    };
+
+    ((match $($base:ident)*) $call:ident! $extra:tt $excl:tt) => {};
+
+    ((match $($base:ident)*) $call:ident! $extra:tt $excl:tt => { $($body:tt)* } $($rest:tt)*) => {
+        $crate::pounded_var_names!{$call! $extra $excl $($body)*}
+        $crate::pattern_var_names!{(match $($base)*) $call! $extra [$($base)*] $($rest)*}
+    };
+
+    ((match $($base:ident)*) $call:ident! $extra:tt $excl:tt if $($rest:tt)*) => {
+        $crate::pattern_var_names!{@guard (match $($base)*) $call! $extra $excl $($rest)*}
+    };
+
+    ((bound) $call:ident! $extra:tt []) => {};
+
+    ((bound) $call:ident! ($($extra:tt)*) [$name:ident $($rest:ident)*]) => {
+        $crate::$call!($($extra)* $name);
+        $crate::pattern_var_names!{(bound) $call! ($($extra)*) [$($rest)*]}
+    };
+
+    (@let [$($pat:tt)*] $k:tt $call:ident! $extra:tt $excl:tt = $($stmt:tt)*) => {
+        $crate::pattern_var_names!{$k $call! $extra $excl $($pat)*}
+    };
+
+    (@let [$($pat:tt)*] $k:tt $call:ident! $extra:tt $excl:tt : $($stmt:tt)*) => {
+        $crate::pattern_var_names!{$k $call! $extra $excl $($pat)*}
+    };
+
+    (@let [$($pat:tt)*] $k:tt $call:ident! $extra:tt $excl:tt $next:tt $($stmt:tt)*) => {
+        $crate::pattern_var_names!{@let [$($pat)* $next] $k $call! $extra $excl $($stmt)*}
+    };
+
+    (@let [$($pat:tt)*] $k:tt $call:ident! $extra:tt $excl:tt) => {
+        $crate::pattern_var_names!{$k $call! $extra $excl $($pat)*}
+    };
+
+    (@guard $k:tt $call:ident! $extra:tt $excl:tt => $($rest:tt)*) => {
+        $crate::pattern_var_names!{$k $call! $extra $excl => $($rest)*}
+    };
+
+    (@guard $k:tt $call:ident! $extra:tt $excl:tt $skip:tt $($rest:tt)*) => {
+        $crate::pattern_var_names!{@guard $k $call! $extra $excl $($rest)*}
+    };
+
+    // An interpolation rather than a binding.
+    ($k:tt $call:ident! $extra:tt $excl:tt # $var:ident $($rest:tt)*) => {
+        $crate::pattern_var_names!{$k $call! $extra $excl $($rest)*}
+    };
+
+    ($k:tt $call:ident! $extra:tt $excl:tt :: $segment:ident $($rest:tt)*) => {
+        $crate::pattern_var_names!{$k $call! $extra $excl $($rest)*}
+    };
+
+    ($k:tt $call:ident! $extra:tt $excl:tt $segment:ident :: $($rest:tt)*) => {
+        $crate::pattern_var_names!{$k $call! $extra $excl :: $($rest)*}
+    };
+
+    ($k:tt $call:ident! $extra:tt $excl:tt $path:ident ( $($inner:tt)* ) $($rest:tt)*) => {
+        $crate::pattern_var_names!{$k $call! $extra $excl $($inner)* $($rest)*}
+    };
+
+    ($k:tt $call:ident! $extra:tt $excl:tt $path:ident { $($inner:tt)* } $($rest:tt)*) => {
+        $crate::pattern_var_names!{$k $call! $extra $excl $($inner)* $($rest)*}
+    };
+
+    ($k:tt $call:ident! $extra:tt $excl:tt $field:ident : $($rest:tt)*) => {
+        $crate::pattern_var_names!{$k $call! $extra $excl $($rest)*}
+    };
+
+    ($k:tt $call:ident! $extra:tt $excl:tt mut $($rest:tt)*) => {
+        $crate::pattern_var_names!{$k $call! $extra $excl $($rest)*}
+    };
+
+    ($k:tt $call:ident! $extra:tt $excl:tt ref $($rest:tt)*) => {
+        $crate::pattern_var_names!{$k $call! $extra $excl $($rest)*}
+    };
+
+    // The unit variant in the prelude, which cannot be shadowed by a binding.
+    ($k:tt $call:ident! $extra:tt $excl:tt None $($rest:tt)*) => {
+        $crate::pattern_var_names!{$k $call! $extra $excl $($rest)*}
+    };
+
+    ($k:tt $call:ident! $extra:tt $excl:tt true $($rest:tt)*) => {
+        $crate::pattern_var_names!{$k $call! $extra $excl $($rest)*}
+    };
+
+    ($k:tt $call:ident! $extra:tt $excl:tt false $($rest:tt)*) => {
+        $crate::pattern_var_names!{$k $call! $extra $excl $($rest)*}
+    };
+
+    ($k:tt $call:ident! $extra:tt [$($excl:ident)*] $name:ident $($rest:tt)*) => {
+        $crate::pattern_var_names!{$k $call! $extra [$($excl)* $name] $($rest)*}
+    };
+
+    ($k:tt $call:ident! $extra:tt $excl:tt ( $($inner:tt)* ) $($rest:tt)*) => {
+        $crate::pattern_var_names!{$k $call! $extra $excl $($inner)* $($rest)*}
+    };
+
+    ($k:tt $call:ident! $extra:tt $excl:tt [ $($inner:tt)* ] $($rest:tt)*) => {
+        $crate::pattern_var_names!{$k $call! $extra $excl $($inner)* $($rest)*}
+    };
+
+    ($k:tt $call:ident! $extra:tt $excl:tt $other:tt $($rest:tt)*) => {
+        $crate::pattern_var_names!{$k $call! $extra $excl $($rest)*}
+    };
 }
 
 #[macro_export]
@@ -727,15 +1451,515 @@ macro_rules! quote_bind_into_iter {
 
 #[macro_export]
 #[doc(hidden)]
-macro_rules! quote_bind_next_or_break {
-    ($var:ident) => {
-        let $var = match $var.next() {
+macro_rules! quote_bind_into_opt {
+    ($has_iter:ident $var:ident) => {
+        // `mut` may be unused if $var occurs multiple times in the list.
+        #[allow(unused_mut)]
+        let (mut $var, i) = (&$crate::__private::RepOption(&$var)).quote_into_opt();
+        let $has_iter = $has_iter | i;
+    };
+}
+
+// A placeholder for a name bound by a nested `#(let pat = expr;)`, declared
+// while binding the variables of the enclosing repetition. The tokens after it
+// refer to the value bound in each iteration rather than to a variable outside
+// of the repetition.
+#[macro_export]
+#[doc(hidden)]
+macro_rules! quote_bind_let {
+    ($var:ident) => {
+        #[allow(unused_variables)]
+        let $var = <$crate::__private::RepBinding>::default();
+    };
+}
+
+#[macro_export]
+#[doc(hidden)]
+macro_rules! quote_bind_next_or_break {
+    ($check:ident $var:ident) => {
+        let $var = match $var.next() {
             Some(_x) => $crate::__private::RepInterp(_x),
-            None => break,
+            None => {
+                $check.ended($crate::__private::stringify!($var));
+                break;
+            }
         };
     };
 }
 
+#[cfg(feature = "checked")]
+#[macro_export]
+#[doc(hidden)]
+macro_rules! quote_check_len {
+    ($check:ident $var:ident) => {
+        if $check.pending($crate::__private::stringify!($var)) {
+            let remaining = $var.quote_remaining();
+            $check.record($crate::__private::stringify!($var), remaining);
+        }
+    };
+}
+
+// With the `checked` feature, verifies after the loop of a repetition that
+// all of the iterators zipped by it had the same length. Optional repetitions
+// are not checked because a mix of `Some` and `None` is their purpose.
+#[cfg(feature = "checked")]
+#[macro_export]
+#[doc(hidden)]
+macro_rules! quote_repetition_check {
+    (quote_bind_into_opt $check:ident $i:ident $excl:tt $($inner:tt)*) => {};
+
+    (quote_bind_into_iter $check:ident $i:ident $excl:tt $($inner:tt)*) => {
+        $check.start($i);
+        $crate::pounded_var_names!{quote_check_len!($check) $excl $($inner)*}
+        $check.finish();
+    };
+}
+
+// Without the `checked` feature there is nothing to verify, and no second pass
+// over the variables of the repetition is expanded.
+#[cfg(not(feature = "checked"))]
+#[macro_export]
+#[doc(hidden)]
+macro_rules! quote_repetition_check {
+    ($($tt:tt)*) => {};
+}
+
+// The loop implementing a repetition, invoked as:
+//
+//     quote_repetition!{$bind $tokens [$($sep)*] [$($trailing)*] [$on_empty] $($inner)*}
+//
+// are the bodies that the repetition is nested in. $bind is the macro that
+// binds each variable interpolated in the repetition before the loop, either
+// quote_bind_into_iter or, for `#(...)?`, quote_bind_into_opt. The separator,
+// which may be any number of tokens including interpolations, the trailing
+// separator emitted after the last iteration, and the function to call if the
+// loop runs zero times are optional.
+#[macro_export]
+#[doc(hidden)]
+macro_rules! quote_repetition {
+    (@impl $bind:ident $tokens:ident [$($sep:tt)*] [$($trailing:tt)*] [$($on_empty:ident)?] [$($index:ident)?] $($inner:tt)*) => {{
+        use $crate::__private::ext::*;
+        let mut _i = 0usize;
+        let mut _check = $crate::__private::RepCheck::new();
+        let has_iter = $crate::__private::HasIterator::<false>;
+        $crate::pounded_var_names!{$bind!(has_iter) [$($index)?] $($inner)*}
+        <_ as $crate::__private::CheckHasIterator<true>>::check(has_iter);
+        // This is `while true` instead of `loop` because if there are no
+        // iterators used inside of this repetition then the body would not
+        // contain any `break`, so the compiler would emit unreachable code
+        // warnings on anything below the loop. We use has_iter to detect and
+        // fail to compile when there are no iterators, so here we just work
+        // around the unneeded extra warning.
+        while true {
+            $crate::pounded_var_names!{quote_bind_next_or_break!(_check) [$($index)?] $($inner)*}
+            $(
+                let $index = $crate::__private::RepIndex(_i);
+            )?
+            $crate::quote_separator!{$tokens _i $($sep)*}
+            _i += 1;
+            $crate::quote_each_token!{$tokens $($inner)*}
+        }
+        $crate::quote_separator!{$tokens _i $($trailing)*}
+        $crate::quote_repetition_check!{$bind _check _i [$($index)?] $($inner)*}
+        $(
+            if _i == 0 {
+                $crate::__private::$on_empty();
+            }
+        )?
+    }};
+
+    // A body that begins with `#@name` binds `name` to the index of each
+    // iteration.
+    ($bind:ident $tokens:ident $sep:tt $trailing:tt $on_empty:tt # @ $index:ident $($inner:tt)*) => {
+        $crate::quote_repetition!{@impl $bind $tokens $sep $trailing $on_empty [$index] $($inner)*}
+    };
+
+    ($bind:ident $tokens:ident $sep:tt $trailing:tt $on_empty:tt $($inner:tt)*) => {
+        $crate::quote_repetition!{@impl $bind $tokens $sep $trailing $on_empty [] $($inner)*}
+    };
+}
+
+// See the explanation on quote_repetition.
+#[macro_export]
+#[doc(hidden)]
+macro_rules! quote_repetition_spanned {
+    (@impl $bind:ident $tokens:ident $span:ident [$($sep:tt)*] [$($trailing:tt)*] [$($on_empty:ident)?] [$($index:ident)?] $($inner:tt)*) => {{
+        use $crate::__private::ext::*;
+        let mut _i = 0usize;
+        let mut _check = $crate::__private::RepCheck::new();
+        let has_iter = $crate::__private::HasIterator::<false>;
+        $crate::pounded_var_names!{$bind!(has_iter) [$($index)?] $($inner)*}
+        <_ as $crate::__private::CheckHasIterator<true>>::check(has_iter);
+        while true {
+            $crate::pounded_var_names!{quote_bind_next_or_break!(_check) [$($index)?] $($inner)*}
+            $(
+                let $index = $crate::__private::RepIndex(_i);
+            )?
+            $crate::quote_separator_spanned!{$tokens $span _i $($sep)*}
+            _i += 1;
+            $crate::quote_each_token_spanned!{$tokens $span $($inner)*}
+        }
+        $crate::quote_separator_spanned!{$tokens $span _i $($trailing)*}
+        $crate::quote_repetition_check!{$bind _check _i [$($index)?] $($inner)*}
+        $(
+            if _i == 0 {
+                $crate::__private::$on_empty();
+            }
+        )?
+    }};
+
+    ($bind:ident $tokens:ident $span:ident $sep:tt $trailing:tt $on_empty:tt # @ $index:ident $($inner:tt)*) => {
+        $crate::quote_repetition_spanned!{@impl $bind $tokens $span $sep $trailing $on_empty [$index] $($inner)*}
+    };
+
+    ($bind:ident $tokens:ident $span:ident $sep:tt $trailing:tt $on_empty:tt $($inner:tt)*) => {
+        $crate::quote_repetition_spanned!{@impl $bind $tokens $span $sep $trailing $on_empty [] $($inner)*}
+    };
+}
+
+// The loop implementing `#(for pat in expr) { ... }`, whose expression may
+// be anything accepted by a repetition, and whose separator is as for
+// quote_repetition. The pattern is split from the expression at the first
+// `in`, rather than parsed as a `pat` fragment, so that a `#(for<'a> ...)`
+// that is not followed by a body can still fall through to a repetition.
+#[macro_export]
+#[doc(hidden)]
+macro_rules! quote_for {
+    (@impl $tokens:ident [$($sep:tt)*] [$($pat:tt)*] [$($expr:tt)*] $($body:tt)*) => {{
+        use $crate::__private::ext::*;
+        let mut _i = 0usize;
+        match ($crate::quote_expr!{[] [] $($expr)*}).quote_into_iter() {
+            (iter, has_iter) => {
+                <_ as $crate::__private::CheckHasIterator<true>>::check(has_iter);
+                for $($pat)* in iter {
+                    $crate::quote_separator!{$tokens _i $($sep)*}
+                    _i += 1;
+                    $crate::quote_each_token!{$tokens $($body)*}
+                }
+            }
+        }
+    }};
+
+    (@pat $tokens:ident $sep:tt $pat:tt [in $($expr:tt)*] $($body:tt)*) => {
+        $crate::quote_for!{@impl $tokens $sep $pat [$($expr)*] $($body)*}
+    };
+
+    (@pat $tokens:ident $sep:tt [$($pat:tt)*] [$next:tt $($rest:tt)*] $($body:tt)*) => {
+        $crate::quote_for!{@pat $tokens $sep [$($pat)* $next] [$($rest)*] $($body)*}
+    };
+
+    (@pat $tokens:ident $sep:tt $pat:tt [] $($body:tt)*) => {
+        $crate::__private::compile_error!("expected `in` after the pattern of `#(for ...)`");
+    };
+
+    ($tokens:ident $sep:tt [$($for:tt)*] $($body:tt)*) => {
+        $crate::quote_for!{@pat $tokens $sep [] [$($for)*] $($body)*}
+    };
+}
+
+// See the explanation on quote_for.
+#[macro_export]
+#[doc(hidden)]
+macro_rules! quote_for_spanned {
+    (@impl $tokens:ident $span:ident [$($sep:tt)*] [$($pat:tt)*] [$($expr:tt)*] $($body:tt)*) => {{
+        use $crate::__private::ext::*;
+        let mut _i = 0usize;
+        match ($crate::quote_expr!{[] [] $($expr)*}).quote_into_iter() {
+            (iter, has_iter) => {
+                <_ as $crate::__private::CheckHasIterator<true>>::check(has_iter);
+                for $($pat)* in iter {
+                    $crate::quote_separator_spanned!{$tokens $span _i $($sep)*}
+                    _i += 1;
+                    $crate::quote_each_token_spanned!{$tokens $span $($body)*}
+                }
+            }
+        }
+    }};
+
+    (@pat $tokens:ident $span:ident $sep:tt $pat:tt [in $($expr:tt)*] $($body:tt)*) => {
+        $crate::quote_for_spanned!{@impl $tokens $span $sep $pat [$($expr)*] $($body)*}
+    };
+
+    (@pat $tokens:ident $span:ident $sep:tt [$($pat:tt)*] [$next:tt $($rest:tt)*] $($body:tt)*) => {
+        $crate::quote_for_spanned!{@pat $tokens $span $sep [$($pat)* $next] [$($rest)*] $($body)*}
+    };
+
+    (@pat $tokens:ident $span:ident $sep:tt $pat:tt [] $($body:tt)*) => {
+        $crate::__private::compile_error!("expected `in` after the pattern of `#(for ...)`");
+    };
+
+    ($tokens:ident $span:ident $sep:tt [$($for:tt)*] $($body:tt)*) => {
+        $crate::quote_for_spanned!{@pat $tokens $span $sep [] [$($for)*] $($body)*}
+    };
+}
+
+// Emits the separator of a repetition before every iteration but the first.
+// A single token is the common case and is emitted directly without going
+// through quote_each_token.
+#[macro_export]
+#[doc(hidden)]
+macro_rules! quote_separator {
+    ($tokens:ident $i:ident) => {};
+
+    ($tokens:ident $i:ident $sep:tt) => {
+        if $i > 0 {
+            $crate::quote_token!{$sep $tokens}
+        }
+    };
+
+    ($tokens:ident $i:ident $($sep:tt)*) => {
+        if $i > 0 {
+            $crate::quote_each_token!{$tokens $($sep)*}
+        }
+    };
+}
+
+// See the explanation on quote_separator.
+#[macro_export]
+#[doc(hidden)]
+macro_rules! quote_separator_spanned {
+    ($tokens:ident $span:ident $i:ident) => {};
+
+    ($tokens:ident $span:ident $i:ident $sep:tt) => {
+        if $i > 0 {
+            $crate::quote_token_spanned!{$sep $tokens $span}
+        }
+    };
+
+    ($tokens:ident $span:ident $i:ident $($sep:tt)*) => {
+        if $i > 0 {
+            $crate::quote_each_token_spanned!{$tokens $span $($sep)*}
+        }
+    };
+}
+
+// Turn the contents of an interpolated expression `#{...}` into a Rust
+// expression by removing the `#` in front of each `#var` inside of it. Those
+// same `#var` are what pounded_var_names finds when the expression is part of a
+// repetition.
+//
+// in:   quote_expr!([] [] #field.name.len() + f(#x))
+// out:  field.name.len() + f(x)
+//
+// The first bracketed tt is a stack of the enclosing groups, each holding the
+// output produced before the group and the input remaining after it. The second
+// is the output produced so far within the innermost group.
+#[macro_export]
+#[doc(hidden)]
+macro_rules! quote_expr {
+    ($stack:tt [$($out:tt)*] # $var:ident $($rest:tt)*) => {
+        $crate::quote_expr!{$stack [$($out)* $var] $($rest)*}
+    };
+
+    ([$($stack:tt)*] [$($out:tt)*] ( $($inner:tt)* ) $($rest:tt)*) => {
+        $crate::quote_expr!{[(paren [$($out)*] [$($rest)*]) $($stack)*] [] $($inner)*}
+    };
+
+    ([$($stack:tt)*] [$($out:tt)*] [ $($inner:tt)* ] $($rest:tt)*) => {
+        $crate::quote_expr!{[(bracket [$($out)*] [$($rest)*]) $($stack)*] [] $($inner)*}
+    };
+
+    ([$($stack:tt)*] [$($out:tt)*] { $($inner:tt)* } $($rest:tt)*) => {
+        $crate::quote_expr!{[(brace [$($out)*] [$($rest)*]) $($stack)*] [] $($inner)*}
+    };
+
+    // Runs of tokens that are neither a `#` nor a group are copied up to four
+    // at a time, together with a `#var` that ends the run, so that a long
+    // expression does not reach the recursion limit.
+    ($stack:tt [$($out:tt)*] $a:tt # $var:ident $($rest:tt)*) => {
+        $crate::quote_expr!{$stack [$($out)* $a $var] $($rest)*}
+    };
+
+    ($stack:tt [$($out:tt)*] $a:tt # $($rest:tt)*) => {
+        $crate::quote_expr!{$stack [$($out)* $a] # $($rest)*}
+    };
+
+    ($stack:tt [$($out:tt)*] $a:tt ( $($group:tt)* ) $($rest:tt)*) => {
+        $crate::quote_expr!{$stack [$($out)* $a] ( $($group)* ) $($rest)*}
+    };
+
+    ($stack:tt [$($out:tt)*] $a:tt [ $($group:tt)* ] $($rest:tt)*) => {
+        $crate::quote_expr!{$stack [$($out)* $a] [ $($group)* ] $($rest)*}
+    };
+
+    ($stack:tt [$($out:tt)*] $a:tt { $($group:tt)* } $($rest:tt)*) => {
+        $crate::quote_expr!{$stack [$($out)* $a] { $($group)* } $($rest)*}
+    };
+
+    ($stack:tt [$($out:tt)*] $a:tt $b:tt # $var:ident $($rest:tt)*) => {
+        $crate::quote_expr!{$stack [$($out)* $a $b $var] $($rest)*}
+    };
+
+    ($stack:tt [$($out:tt)*] $a:tt $b:tt # $($rest:tt)*) => {
+        $crate::quote_expr!{$stack [$($out)* $a $b] # $($rest)*}
+    };
+
+    ($stack:tt [$($out:tt)*] $a:tt $b:tt ( $($group:tt)* ) $($rest:tt)*) => {
+        $crate::quote_expr!{$stack [$($out)* $a $b] ( $($group)* ) $($rest)*}
+    };
+
+    ($stack:tt [$($out:tt)*] $a:tt $b:tt [ $($group:tt)* ] $($rest:tt)*) => {
+        $crate::quote_expr!{$stack [$($out)* $a $b] [ $($group)* ] $($rest)*}
+    };
+
+    ($stack:tt [$($out:tt)*] $a:tt $b:tt { $($group:tt)* } $($rest:tt)*) => {
+        $crate::quote_expr!{$stack [$($out)* $a $b] { $($group)* } $($rest)*}
+    };
+
+    ($stack:tt [$($out:tt)*] $a:tt $b:tt $c:tt # $var:ident $($rest:tt)*) => {
+        $crate::quote_expr!{$stack [$($out)* $a $b $c $var] $($rest)*}
+    };
+
+    ($stack:tt [$($out:tt)*] $a:tt $b:tt $c:tt # $($rest:tt)*) => {
+        $crate::quote_expr!{$stack [$($out)* $a $b $c] # $($rest)*}
+    };
+
+    ($stack:tt [$($out:tt)*] $a:tt $b:tt $c:tt ( $($group:tt)* ) $($rest:tt)*) => {
+        $crate::quote_expr!{$stack [$($out)* $a $b $c] ( $($group)* ) $($rest)*}
+    };
+
+    ($stack:tt [$($out:tt)*] $a:tt $b:tt $c:tt [ $($group:tt)* ] $($rest:tt)*) => {
+        $crate::quote_expr!{$stack [$($out)* $a $b $c] [ $($group)* ] $($rest)*}
+    };
+
+    ($stack:tt [$($out:tt)*] $a:tt $b:tt $c:tt { $($group:tt)* } $($rest:tt)*) => {
+        $crate::quote_expr!{$stack [$($out)* $a $b $c] { $($group)* } $($rest)*}
+    };
+
+    ($stack:tt [$($out:tt)*] $a:tt $b:tt $c:tt $d:tt $($rest:tt)*) => {
+        $crate::quote_expr!{$stack [$($out)* $a $b $c $d] $($rest)*}
+    };
+
+    ($stack:tt [$($out:tt)*] $other:tt $($rest:tt)*) => {
+        $crate::quote_expr!{$stack [$($out)* $other] $($rest)*}
+    };
+
+    ([(paren [$($outer:tt)*] [$($rest:tt)*]) $($stack:tt)*] [$($out:tt)*]) => {
+        $crate::quote_expr!{[$($stack)*] [$($outer)* ( $($out)* )] $($rest)*}
+    };
+
+    ([(bracket [$($outer:tt)*] [$($rest:tt)*]) $($stack:tt)*] [$($out:tt)*]) => {
+        $crate::quote_expr!{[$($stack)*] [$($outer)* [ $($out)* ]] $($rest)*}
+    };
+
+    ([(brace [$($outer:tt)*] [$($rest:tt)*]) $($stack:tt)*] [$($out:tt)*]) => {
+        $crate::quote_expr!{[$($stack)*] [$($outer)* { $($out)* }] $($rest)*}
+    };
+
+    ([] [$($out:tt)*]) => {
+        $($out)*
+    };
+}
+
+// Concatenate the fragments of an `#(ident ...)` into a single identifier,
+// whose span is that of the first interpolated fragment which has one, or else
+// the given default.
+//
+// in:   quote_ident!(None; get_ #name _mut)
+// out:  { let mut _ident = IdentConcat::default();
+//         _ident.push_str("get_"); _ident.push(&name); _ident.push_str("_mut");
+//         _ident.finish(None) }
+#[macro_export]
+#[doc(hidden)]
+macro_rules! quote_ident {
+    ($span:expr; $($frags:tt)*) => {{
+        let mut _ident = $crate::__private::IdentConcat::default();
+        $crate::quote_ident_fragments!{_ident $($frags)*}
+        _ident.finish($span)
+    }};
+}
+
+#[macro_export]
+#[doc(hidden)]
+macro_rules! quote_ident_fragments {
+    ($ident:ident) => {};
+
+    ($ident:ident # $var:ident $($rest:tt)*) => {
+        $ident.push(&$var);
+        $crate::quote_ident_fragments!{$ident $($rest)*}
+    };
+
+    ($ident:ident # { $($expr:tt)* } $($rest:tt)*) => {
+        $ident.push(&($crate::quote_expr!{[] [] $($expr)*}));
+        $crate::quote_ident_fragments!{$ident $($rest)*}
+    };
+
+    ($ident:ident $frag:ident $($rest:tt)*) => {
+        $ident.push_str($crate::__private::stringify!($frag));
+        $crate::quote_ident_fragments!{$ident $($rest)*}
+    };
+
+    ($ident:ident $frag:literal $($rest:tt)*) => {
+        const _: () = $crate::__private::assert!(
+            $crate::__private::is_ident_literal($crate::__private::stringify!($frag)),
+            "{}",
+            $crate::__private::concat!(
+                "unexpected `",
+                $crate::__private::stringify!($frag),
+                "` in `#(ident ...)`, expected an identifier, an integer literal, `#var` or `#{...}`",
+            ),
+        );
+        $ident.push_str($crate::__private::stringify!($frag));
+        $crate::quote_ident_fragments!{$ident $($rest)*}
+    };
+
+    ($ident:ident $other:tt $($rest:tt)*) => {
+        $crate::__private::compile_error!($crate::__private::concat!(
+            "unexpected `",
+            $crate::__private::stringify!($other),
+            "` in `#(ident ...)`, expected an identifier, an integer literal, `#var` or `#{...}`",
+        ));
+    };
+}
+
+// The doc comments of a `#(doc #var ... /// ...)`, each of whose text may refer
+// to the listed variables as `#var`. A name in the text that is not listed is a
+// compile error.
+//
+// in:   quote_doc!(_s false [name] r" Returns #name.")
+// out:  const _: () = check_doc(r" Returns #name.", &["name"]);
+//       push_doc(&mut _s, false, r" Returns #name.", |_name| ...);
+#[macro_export]
+#[doc(hidden)]
+macro_rules! quote_doc {
+    ($tokens:ident $inner:literal $vars:tt $($lit:literal)*) => {
+        $(
+            $crate::quote_doc!{@line $tokens $inner $vars $lit}
+        )*
+    };
+
+    ($tokens:ident $span:ident $inner:literal $vars:tt $($lit:literal)*) => {
+        $(
+            $crate::quote_doc!{@line $tokens $span $inner $vars $lit}
+        )*
+    };
+
+    (@line $tokens:ident $inner:literal [$($var:ident)*] $lit:literal) => {
+        const _: () = $crate::__private::check_doc($lit, &[$($crate::__private::stringify!($var)),*]);
+        $crate::__private::push_doc(&mut $tokens, $inner, $lit, $crate::quote_doc!{@lookup $($var)*});
+    };
+
+    (@line $tokens:ident $span:ident $inner:literal [$($var:ident)*] $lit:literal) => {
+        const _: () = $crate::__private::check_doc($lit, &[$($crate::__private::stringify!($var)),*]);
+        $crate::__private::push_doc_spanned(&mut $tokens, $span, $inner, $lit, $crate::quote_doc!{@lookup $($var)*});
+    };
+
+    (@lookup $($var:ident)*) => {
+        |_name: &str| {
+            #[allow(unused_imports)]
+            use $crate::__private::doc_ext::*;
+            $(
+                if _name == $crate::__private::stringify!($var) {
+                    return $crate::__private::Option::Some(
+                        (&&&$crate::__private::DocValue(&$var)).quote_doc_value(),
+                    );
+                }
+            )*
+            $crate::__private::Option::None
+        }
+    };
+}
+
 // The obvious way to write this macro is as a tt muncher. This implementation
 // does something more complex for two reasons.
 //
@@ -786,14 +2010,22 @@ macro_rules! quote_bind_next_or_break {
 //
 // Without having used muncher-style recursion, we get one invocation of
 // quote_token_with_context for each original tt, with three tts of context on
-// either side. This is enough for the longest possible interpolation form (a
-// repetition with separator, as in `# (#var) , *`) to be fully represented with
-// the first or last tt in the middle.
+// either side. This is enough for a repetition with separator, as in
+// `# (#var) , *`, to be fully represented with the first or last tt in the
+// middle. The longest interpolation forms are one tt longer, as in
+// `# (#var) # sep *`, and are instead interpolated from the parenthesized group
+// in the middle, which has both the `#` and the last tt in view.
 //
 // The middle tt (surrounded by parentheses) is the tt being processed.
 //
-//   - When it is a `#`, quote_token_with_context can do an interpolation. The
-//     interpolation kind will depend on the three subsequent tts.
+//   - When it is a `#` or one of the three tts after a `#`, it may be part of
+//     an interpolation. This is rare compared to ordinary tokens, so the many
+//     rules for it are kept out of line in quote_interpolation_with_context.
+//
+//   - When it is a `#`, quote_interpolation_with_context can do an
+//     interpolation. The interpolation kind will depend on the three
+//     subsequent tts. If the next tt is a parenthesized group, the
+//     interpolation is done one step later, from the group.
 //
 //   - When it is within a later part of an interpolation, it can be ignored
 //     because the interpolation has already been done.
@@ -871,50 +2103,343 @@ macro_rules! quote_tokens_with_context_spanned {
 #[macro_export]
 #[doc(hidden)]
 macro_rules! quote_token_with_context {
+    // A `#`, or a tt within three steps after one, which may be part of an
+    // interpolation. These are rare compared to ordinary tokens, so the rules
+    // for them are kept out of line and an ordinary token only has to fail to
+    // match the few rules in this macro.
+    ($tokens:ident $b3:tt $b2:tt $b1:tt (#) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_interpolation_with_context!{$tokens $b3 $b2 $b1 (#) $a1 $a2 $a3}
+    };
+    ($tokens:ident $b3:tt $b2:tt # $curr:tt $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_interpolation_with_context!{$tokens $b3 $b2 # $curr $a1 $a2 $a3}
+    };
+    ($tokens:ident $b3:tt # $b1:tt $curr:tt $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_interpolation_with_context!{$tokens $b3 # $b1 $curr $a1 $a2 $a3}
+    };
+    ($tokens:ident # $b2:tt $b1:tt $curr:tt $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_interpolation_with_context!{$tokens # $b2 $b1 $curr $a1 $a2 $a3}
+    };
+
+    // The last two tts of a form that is too long for the `#` to still be in
+    // view, as in `#(#var),?*` or `#(for ...) {...},*`, which are likewise
+    // handled out of line.
+    ($tokens:ident $b3:tt ( $($inner:tt)* ) $sep:tt (?) * $a2:tt $a3:tt) => {
+        $crate::quote_interpolation_with_context!{$tokens $b3 ( $($inner)* ) $sep (?) * $a2 $a3}
+    };
+    ($tokens:ident ( $($inner:tt)* ) $sep:tt ? (*) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_interpolation_with_context!{$tokens ( $($inner)* ) $sep ? (*) $a1 $a2 $a3}
+    };
+    ($tokens:ident (for $($pat:tt)*) { $($body:tt)* } $sep:tt (*) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_interpolation_with_context!{$tokens (for $($pat)*) { $($body)* } $sep (*) $a1 $a2 $a3}
+    };
+    ($tokens:ident $b3:tt (for $($pat:tt)*) { $($body:tt)* } $curr:tt * $a2:tt $a3:tt) => {
+        $crate::quote_interpolation_with_context!{$tokens $b3 (for $($pat)*) { $($body)* } $curr * $a2 $a3}
+    };
+
     // Unparenthesized `@` indicates this call does not correspond to one of the
     // original input tokens. Ignore it.
     ($tokens:ident $b3:tt $b2:tt $b1:tt @ $a1:tt $a2:tt $a3:tt) => {};
 
-    // A repetition with no separator.
-    ($tokens:ident $b3:tt $b2:tt $b1:tt (#) ( $($inner:tt)* ) * $a3:tt) => {{
-        use $crate::__private::ext::*;
-        let has_iter = $crate::__private::HasIterator::<false>;
-        $crate::pounded_var_names!{quote_bind_into_iter!(has_iter) () $($inner)*}
-        <_ as $crate::__private::CheckHasIterator<true>>::check(has_iter);
-        // This is `while true` instead of `loop` because if there are no
-        // iterators used inside of this repetition then the body would not
-        // contain any `break`, so the compiler would emit unreachable code
-        // warnings on anything below the loop. We use has_iter to detect and
-        // fail to compile when there are no iterators, so here we just work
-        // around the unneeded extra warning.
-        while true {
-            $crate::pounded_var_names!{quote_bind_next_or_break!() () $($inner)*}
-            $crate::quote_each_token!{$tokens $($inner)*}
+    // An ordinary token, not part of any interpolation.
+    ($tokens:ident $b3:tt $b2:tt $b1:tt ($curr:tt) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_token!{$curr $tokens}
+    };
+}
+
+// See the explanation on quote_token_with_context.
+#[macro_export]
+#[doc(hidden)]
+macro_rules! quote_token_with_context_spanned {
+    ($tokens:ident $span:ident $b3:tt $b2:tt $b1:tt (#) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_interpolation_with_context_spanned!{$tokens $span $b3 $b2 $b1 (#) $a1 $a2 $a3}
+    };
+    ($tokens:ident $span:ident $b3:tt $b2:tt # $curr:tt $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_interpolation_with_context_spanned!{$tokens $span $b3 $b2 # $curr $a1 $a2 $a3}
+    };
+    ($tokens:ident $span:ident $b3:tt # $b1:tt $curr:tt $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_interpolation_with_context_spanned!{$tokens $span $b3 # $b1 $curr $a1 $a2 $a3}
+    };
+    ($tokens:ident $span:ident # $b2:tt $b1:tt $curr:tt $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_interpolation_with_context_spanned!{$tokens $span # $b2 $b1 $curr $a1 $a2 $a3}
+    };
+
+    ($tokens:ident $span:ident $b3:tt ( $($inner:tt)* ) $sep:tt (?) * $a2:tt $a3:tt) => {
+        $crate::quote_interpolation_with_context_spanned!{$tokens $span $b3 ( $($inner)* ) $sep (?) * $a2 $a3}
+    };
+    ($tokens:ident $span:ident ( $($inner:tt)* ) $sep:tt ? (*) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_interpolation_with_context_spanned!{$tokens $span ( $($inner)* ) $sep ? (*) $a1 $a2 $a3}
+    };
+    ($tokens:ident $span:ident (for $($pat:tt)*) { $($body:tt)* } $sep:tt (*) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_interpolation_with_context_spanned!{$tokens $span (for $($pat)*) { $($body)* } $sep (*) $a1 $a2 $a3}
+    };
+    ($tokens:ident $span:ident $b3:tt (for $($pat:tt)*) { $($body:tt)* } $curr:tt * $a2:tt $a3:tt) => {
+        $crate::quote_interpolation_with_context_spanned!{$tokens $span $b3 (for $($pat)*) { $($body)* } $curr * $a2 $a3}
+    };
+
+    ($tokens:ident $span:ident $b3:tt $b2:tt $b1:tt @ $a1:tt $a2:tt $a3:tt) => {};
+
+    ($tokens:ident $span:ident $b3:tt $b2:tt $b1:tt ($curr:tt) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_token_spanned!{$curr $tokens $span}
+    };
+}
+
+// The rules of quote_token_with_context for a tt that may be part of an
+// interpolation. See the explanation on quote_each_token.
+#[macro_export]
+#[doc(hidden)]
+macro_rules! quote_interpolation_with_context {
+    // A conditional, emitted from the tt after its body because that is the
+    // first point at which it is known whether an `#(else)` comes next.
+    ($tokens:ident # (if $($cond:tt)*) { $($then:tt)* } (#) (else) { $($else:tt)* } $a3:tt) => {{
+        let _cond = $crate::__private::quote_cond($crate::quote_expr!{[] [] $($cond)*});
+        if _cond {
+            $crate::quote_each_token!{$tokens $($then)*}
+        } else {
+            $crate::quote_each_token!{$tokens $($else)*}
         }
     }};
+    // ... or without an else, after which the current tt is processed as if
+    // the conditional were not there.
+    ($tokens:ident # (if $($cond:tt)*) { $($then:tt)* } $curr:tt $a1:tt $a2:tt $a3:tt) => {
+        {
+            let _cond = $crate::__private::quote_cond($crate::quote_expr!{[] [] $($cond)*});
+            if _cond {
+                $crate::quote_each_token!{$tokens $($then)*}
+            }
+        }
+        $crate::quote_token_with_context!{$tokens @ @ @ $curr $a1 $a2 $a3}
+    };
+
+    // Unparenthesized `@`, as in quote_token_with_context.
+    ($tokens:ident $b3:tt $b2:tt $b1:tt @ $a1:tt $a2:tt $a3:tt) => {};
+
+    // The start of a conditional, which is emitted later as described above.
+    ($tokens:ident $b3:tt $b2:tt $b1:tt (#) (if $($cond:tt)*) { $($then:tt)* } $a3:tt) => {};
     // ... and one step later.
-    ($tokens:ident $b3:tt $b2:tt # (( $($inner:tt)* )) * $a2:tt $a3:tt) => {};
+    ($tokens:ident $b3:tt $b2:tt # ((if $($cond:tt)*)) { $($then:tt)* } $a2:tt $a3:tt) => {};
     // ... and one step later.
-    ($tokens:ident $b3:tt # ( $($inner:tt)* ) (*) $a1:tt $a2:tt $a3:tt) => {};
+    ($tokens:ident $b3:tt # (if $($cond:tt)*) ({ $($then:tt)* }) $a1:tt $a2:tt $a3:tt) => {};
+    // ... and the second tt after the body, which is an ordinary token even
+    // where it would end a repetition, as the `*` in `#(if ...) { ... } ?*`.
+    ($tokens:ident (if $($cond:tt)*) { $($then:tt)* } $b1:tt ($curr:tt) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_token_with_context!{$tokens @ @ $b1 ($curr) $a1 $a2 $a3}
+    };
 
-    // A repetition with separator.
-    ($tokens:ident $b3:tt $b2:tt $b1:tt (#) ( $($inner:tt)* ) $sep:tt *) => {{
-        use $crate::__private::ext::*;
-        let mut _first = true;
-        let has_iter = $crate::__private::HasIterator::<false>;
-        $crate::pounded_var_names!{quote_bind_into_iter!(has_iter) () $($inner)*}
-        <_ as $crate::__private::CheckHasIterator<true>>::check(has_iter);
-        while true {
-            $crate::pounded_var_names!{quote_bind_next_or_break!() () $($inner)*}
-            if !_first {
-                $crate::quote_token!{$sep $tokens}
-            }
-            _first = false;
-            $crate::quote_each_token!{$tokens $($inner)*}
+    // An else that does not directly follow a conditional.
+    ($tokens:ident $b3:tt $b2:tt $b1:tt (#) (else) { $($else:tt)* } $a3:tt) => {
+        $crate::__private::compile_error!("`#(else)` must directly follow the body of an `#(if ...)`");
+    };
+    // ... and one step later.
+    ($tokens:ident $b3:tt $b2:tt # ((else)) { $($else:tt)* } $a2:tt $a3:tt) => {};
+    // ... and one step later.
+    ($tokens:ident $b3:tt # (else) ({ $($else:tt)* }) $a1:tt $a2:tt $a3:tt) => {};
+    // ... and the two tts after the body, which are ordinary tokens even where
+    // they would end a repetition, as the `+` in `#(else) { ... } + 1`.
+    ($tokens:ident # (else) { $($else:tt)* } ($curr:tt) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_token_with_context!{$tokens @ @ @ ($curr) $a1 $a2 $a3}
+    };
+    ($tokens:ident (else) { $($else:tt)* } $b1:tt ($curr:tt) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_token_with_context!{$tokens @ @ $b1 ($curr) $a1 $a2 $a3}
+    };
+
+    // A match, whose arms are each of the form `Pattern => { tokens }`.
+    ($tokens:ident $b3:tt $b2:tt $b1:tt (#) (match $($expr:tt)*) { $( $pat:pat $(if $guard:expr)? => { $($body:tt)* } $(,)? )* } $a3:tt) => {
+        match $crate::quote_expr!{[] [] $($expr)*} {
+            $(
+                $pat $(if $guard)? => {
+                    $crate::quote_each_token!{$tokens $($body)*}
+                }
+            )*
         }
+    };
+    ($tokens:ident $b3:tt $b2:tt $b1:tt (#) (match $($expr:tt)*) { $($arms:tt)* } $a3:tt) => {
+        $crate::__private::compile_error!("each arm of `#(match ...)` must be written as `Pattern => { ... }`");
+    };
+    // ... and one step later.
+    ($tokens:ident $b3:tt $b2:tt # ((match $($expr:tt)*)) { $($arms:tt)* } $a2:tt $a3:tt) => {};
+    // ... and one step later.
+    ($tokens:ident $b3:tt # (match $($expr:tt)*) ({ $($arms:tt)* }) $a1:tt $a2:tt $a3:tt) => {};
+    // ... and the two tts after the body, which are ordinary tokens even where
+    // they would end a repetition, as the `+` in `#(match ...) { ... } + 1`.
+    ($tokens:ident # (match $($expr:tt)*) { $($arms:tt)* } ($curr:tt) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_token_with_context!{$tokens @ @ @ ($curr) $a1 $a2 $a3}
+    };
+    ($tokens:ident (match $($expr:tt)*) { $($arms:tt)* } $b1:tt ($curr:tt) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_token_with_context!{$tokens @ @ $b1 ($curr) $a1 $a2 $a3}
+    };
+
+    // A region whose tokens are spanned with the given span, as if written in
+    // quote_spanned!.
+    ($tokens:ident $b3:tt $b2:tt $b1:tt (#) (span = $($span:tt)*) { $($body:tt)* } $a3:tt) => {{
+        let _span: $crate::__private::Span = $crate::__private::get_span($crate::quote_expr!{[] [] $($span)*}).__into_span();
+        $crate::quote_each_token_spanned!{$tokens _span $($body)*}
     }};
     // ... and one step later.
-    ($tokens:ident $b3:tt $b2:tt # (( $($inner:tt)* )) $sep:tt * $a3:tt) => {};
+    ($tokens:ident $b3:tt $b2:tt # ((span = $($span:tt)*)) { $($body:tt)* } $a2:tt $a3:tt) => {};
+    // ... and one step later.
+    ($tokens:ident $b3:tt # (span = $($span:tt)*) ({ $($body:tt)* }) $a1:tt $a2:tt $a3:tt) => {};
+    // ... and the two tts after the body, which are ordinary tokens even where
+    // they would end a repetition, as the `*` in `#(span = ...) { ... } * 2`.
+    ($tokens:ident # (span = $($span:tt)*) { $($body:tt)* } ($curr:tt) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_token_with_context!{$tokens @ @ @ ($curr) $a1 $a2 $a3}
+    };
+    ($tokens:ident (span = $($span:tt)*) { $($body:tt)* } $b1:tt ($curr:tt) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_token_with_context!{$tokens @ @ $b1 ($curr) $a1 $a2 $a3}
+    };
+
+    // The `#` before any other parenthesized group, which is interpolated one
+    // step later from the group. From there both the `#` and the three tts
+    // after the group are in view, which is as far as the longest forms go.
+    ($tokens:ident $b3:tt $b2:tt $b1:tt (#) ( $($inner:tt)* ) $a2:tt $a3:tt) => {};
+
+    // A repetition with no separator.
+    ($tokens:ident $b3:tt $b2:tt # (( $($inner:tt)* )) * $a2:tt $a3:tt) => {
+        $crate::quote_repetition!{quote_bind_into_iter $tokens [] [] [] $($inner)*}
+    };
+    // ... and one step later.
+    ($tokens:ident $b3:tt # ( $($inner:tt)* ) (*) $a1:tt $a2:tt $a3:tt) => {};
+
+    // A repetition over the elements of an expression, whose parts are named
+    // by a pattern as in `#(for (name, ty) in pairs) { #name: #ty },*`.
+    ($tokens:ident $b3:tt $b2:tt # ((for $($for:tt)*)) { $($body:tt)* } * $a3:tt) => {
+        $crate::quote_for!{$tokens [] [$($for)*] $($body)*}
+    };
+    ($tokens:ident $b3:tt $b2:tt # ((for $($for:tt)*)) { $($body:tt)* } # [ $($sep:tt)* ]) => {};
+    ($tokens:ident $b3:tt $b2:tt # ((for $($for:tt)*)) { $($body:tt)* } $sep:tt *) => {
+        $crate::quote_for!{$tokens [$sep] [$($for)*] $($body)*}
+    };
+    ($tokens:ident $b3:tt $b2:tt # ((for $($pat:tt)*)) { $($body:tt)* } $a2:tt $a3:tt) => {
+        $crate::__private::compile_error!("expected `*` or a separator and `*` after the body of `#(for ...)`");
+    };
+    // ... and one step later, which is where a separator of several tokens
+    // is in view.
+    ($tokens:ident $b3:tt # (for $($for:tt)*) ({ $($body:tt)* }) # [ $($sep:tt)* ] *) => {
+        $crate::quote_for!{$tokens [$($sep)*] [$($for)*] $($body)*}
+    };
+    ($tokens:ident $b3:tt # (for $($for:tt)*) ({ $($body:tt)* }) # [ $($sep:tt)* ] $a3:tt) => {
+        $crate::__private::compile_error!("expected `*` or a separator and `*` after the body of `#(for ...)`");
+    };
+    ($tokens:ident $b3:tt # (for $($pat:tt)*) ({ $($body:tt)* }) $a1:tt $a2:tt $a3:tt) => {};
+    // ... and one step later, which also emits the next tt if it is an
+    // ordinary `*` after `#(for ...) {...}*` (compare `#(var)**` below).
+    ($tokens:ident # (for $($pat:tt)*) { $($body:tt)* } (*) * $a2:tt $a3:tt) => {
+        $crate::quote_token!{* $tokens}
+    };
+    ($tokens:ident # (for $($pat:tt)*) { $($body:tt)* } ($sep:tt) $a1:tt $a2:tt $a3:tt) => {};
+    // ... and one step later, where the `#` is no longer in view.
+    ($tokens:ident (for $($pat:tt)*) { $($body:tt)* } $sep:tt (*) $a1:tt $a2:tt $a3:tt) => {};
+    // (Without the `#`, the tts are ordinary tokens and the last one is
+    // emitted together with the one before it.)
+    ($tokens:ident $b3:tt (for $($pat:tt)*) { $($body:tt)* } ($sep:tt) * $a2:tt $a3:tt) => {
+        $crate::quote_token!{$sep $tokens}
+        $crate::quote_token!{* $tokens}
+    };
+
+    // A repetition with a trailing separator after the last element, which is
+    // only emitted if the repetition is nonempty.
+    ($tokens:ident $b3:tt $b2:tt # (( $($inner:tt)* )) $sep:tt ? *) => {
+        $crate::quote_repetition!{quote_bind_into_iter $tokens [$sep] [$sep] [] $($inner)*}
+    };
+    // ... and one step later.
+    ($tokens:ident $b3:tt # ( $($inner:tt)* ) ($sep:tt) ? * $a3:tt) => {};
+    // ... and one step later.
+    ($tokens:ident # ( $($inner:tt)* ) $sep:tt (?) * $a2:tt $a3:tt) => {};
+    // ... and one step later, where the `#` is no longer in view.
+    ($tokens:ident ( $($inner:tt)* ) $sep:tt ? (*) $a1:tt $a2:tt $a3:tt) => {};
+    // (Without the `#`, the tts are ordinary tokens and the last one is
+    // emitted together with the one before it.)
+    ($tokens:ident $b3:tt ( $($inner:tt)* ) $sep:tt (?) * $a2:tt $a3:tt) => {
+        $crate::quote_token!{? $tokens}
+        $crate::quote_token!{* $tokens}
+    };
+
+    // A repetition with a separator of several tokens written inside of
+    // brackets after a `#`, as in `#(#var)#[, +]*`, which is emitted from the
+    // bracketed tt where the whole form is in view, as is the nonempty
+    // `#(#var)#[, +]+` and `#(#var)#[, +]?*` with a trailing separator as for
+    // `#(#var),?*`.
+    ($tokens:ident $b3:tt $b2:tt # (( $($inner:tt)* )) # [ $($sep:tt)* ] *) => {};
+    ($tokens:ident $b3:tt $b2:tt # (( $($inner:tt)* )) # [ $($sep:tt)* ] +) => {};
+    ($tokens:ident $b3:tt $b2:tt # (( $($inner:tt)* )) # [ $($sep:tt)* ] ?) => {};
+    // ... and one step later, which emits the tts so far as ordinary tokens if
+    // the `?` is not followed by `*` after all.
+    ($tokens:ident $b3:tt # ( $($inner:tt)* ) (#) [ $($sep:tt)* ] * $a3:tt) => {};
+    ($tokens:ident $b3:tt # ( $($inner:tt)* ) (#) [ $($sep:tt)* ] + $a3:tt) => {};
+    ($tokens:ident $b3:tt # ( $($inner:tt)* ) (#) [ $($sep:tt)* ] ? *) => {};
+    ($tokens:ident $b3:tt # ( $($inner:tt)* ) (#) [ $($sep:tt)* ] ? $a3:tt) => {
+        $crate::quote_interpolation_with_context!{$tokens @ @ # (( $($inner)* )) @ @ @}
+        $crate::quote_token!{# $tokens}
+    };
+    // ... and one step later.
+    ($tokens:ident # ( $($inner:tt)* ) # ([ $($sep:tt)* ]) ? * $a3:tt) => {
+        $crate::quote_repetition!{quote_bind_into_iter $tokens [$($sep)*] [$($sep)*] [] $($inner)*}
+    };
+    ($tokens:ident # ( $($inner:tt)* ) # ([ $($sep:tt)* ]) * $a2:tt $a3:tt) => {
+        $crate::quote_repetition!{quote_bind_into_iter $tokens [$($sep)*] [] [] $($inner)*}
+    };
+    ($tokens:ident # ( $($inner:tt)* ) # ([ $($sep:tt)* ]) + $a2:tt $a3:tt) => {
+        $crate::quote_repetition!{quote_bind_into_iter $tokens [$($sep)*] [] [empty_repetition] $($inner)*}
+    };
+    // ... and likewise after `#(for ...) {...}`, which is emitted one step
+    // earlier from its body.
+    ($tokens:ident (for $($pat:tt)*) { $($body:tt)* } # ([ $($sep:tt)* ]) * $a2:tt $a3:tt) => {};
+    // (Without the form before it, a `#` and a bracketed group are ordinary
+    // tokens or an escaped `#[[ident]]`, and the `*`, `+` or `? *` after them
+    // is emitted together with the group.)
+    ($tokens:ident $b3:tt $b2:tt # ([ $($sep:tt)* ]) ? * $a3:tt) => {
+        $crate::quote_interpolation_with_context!{$tokens @ @ # ([ $($sep)* ]) @ @ @}
+        $crate::quote_token!{? $tokens}
+        $crate::quote_token!{* $tokens}
+    };
+    ($tokens:ident $b3:tt $b2:tt # ([ $($sep:tt)* ]) * $a2:tt $a3:tt) => {
+        $crate::quote_interpolation_with_context!{$tokens @ @ # ([ $($sep)* ]) @ @ @}
+        $crate::quote_token!{* $tokens}
+    };
+    ($tokens:ident $b3:tt $b2:tt # ([ $($sep:tt)* ]) + $a2:tt $a3:tt) => {
+        $crate::quote_interpolation_with_context!{$tokens @ @ # ([ $($sep)* ]) @ @ @}
+        $crate::quote_token!{+ $tokens}
+    };
+    // ... and one step later.
+    ($tokens:ident $b3:tt # [ $($sep:tt)* ] (*) $a1:tt $a2:tt $a3:tt) => {};
+    ($tokens:ident $b3:tt # [ $($sep:tt)* ] (+) $a1:tt $a2:tt $a3:tt) => {};
+    ($tokens:ident $b3:tt # [ $($sep:tt)* ] (?) * $a2:tt $a3:tt) => {};
+    // ... and one step later.
+    ($tokens:ident # [ $($sep:tt)* ] ? (*) $a1:tt $a2:tt $a3:tt) => {};
+
+    // A repetition with an interpolated separator.
+    ($tokens:ident $b3:tt $b2:tt # (( $($inner:tt)* )) # $sep:ident *) => {
+        $crate::quote_repetition!{quote_bind_into_iter $tokens [# $sep] [] [] $($inner)*}
+    };
+    // ... and one step later.
+    ($tokens:ident $b3:tt # ( $($inner:tt)* ) (#) $sep:ident * $a3:tt) => {};
+    // ... and one step later.
+    ($tokens:ident # ( $($inner:tt)* ) # ($sep:ident) * $a2:tt $a3:tt) => {};
+    // ... and one step later, where the `#` is no longer in view.
+    ($tokens:ident ( $($inner:tt)* ) # $sep:ident (*) $a1:tt $a2:tt $a3:tt) => {};
+    // (Without the `#`, the `*` is an ordinary token after an interpolation
+    // and is emitted one step earlier.)
+    ($tokens:ident $b3:tt ( $($inner:tt)* ) # ($sep:ident) * $a2:tt $a3:tt) => {
+        $crate::quote_token!{* $tokens}
+    };
+
+    // A nonempty repetition with an interpolated separator.
+    ($tokens:ident $b3:tt $b2:tt # (( $($inner:tt)* )) # $sep:ident +) => {
+        $crate::quote_repetition!{quote_bind_into_iter $tokens [# $sep] [] [empty_repetition] $($inner)*}
+    };
+    // ... and one step later.
+    ($tokens:ident $b3:tt # ( $($inner:tt)* ) (#) $sep:ident + $a3:tt) => {};
+    // ... and one step later.
+    ($tokens:ident # ( $($inner:tt)* ) # ($sep:ident) + $a2:tt $a3:tt) => {};
+    // ... and one step later, as above.
+    ($tokens:ident ( $($inner:tt)* ) # $sep:ident (+) $a1:tt $a2:tt $a3:tt) => {};
+    ($tokens:ident $b3:tt ( $($inner:tt)* ) # ($sep:ident) + $a2:tt $a3:tt) => {
+        $crate::quote_token!{+ $tokens}
+    };
+
+    // A repetition with separator.
+    ($tokens:ident $b3:tt $b2:tt # (( $($inner:tt)* )) $sep:tt * $a3:tt) => {
+        $crate::quote_repetition!{quote_bind_into_iter $tokens [$sep] [] [] $($inner)*}
+    };
     // ... and one step later.
     ($tokens:ident $b3:tt # ( $($inner:tt)* ) ($sep:tt) * $a2:tt $a3:tt) => {};
     // (A special case for `#(var)**`, where the first `*` is treated as the
@@ -926,6 +2451,104 @@ macro_rules! quote_token_with_context {
     // ... and one step later.
     ($tokens:ident # ( $($inner:tt)* ) $sep:tt (*) $a1:tt $a2:tt $a3:tt) => {};
 
+    // An optional repetition, which is emitted at most once according to
+    // whether the `Option` values interpolated inside of it are `Some`.
+    ($tokens:ident $b3:tt $b2:tt # (( $($inner:tt)* )) ? $a2:tt $a3:tt) => {
+        $crate::quote_repetition!{quote_bind_into_opt $tokens [] [] [] $($inner)*}
+    };
+    // ... and one step later.
+    ($tokens:ident $b3:tt # ( $($inner:tt)* ) (?) $a1:tt $a2:tt $a3:tt) => {};
+
+    // A nonempty repetition with no separator.
+    ($tokens:ident $b3:tt $b2:tt # (( $($inner:tt)* )) + $a2:tt $a3:tt) => {
+        $crate::quote_repetition!{quote_bind_into_iter $tokens [] [] [empty_repetition] $($inner)*}
+    };
+    // ... and one step later.
+    ($tokens:ident $b3:tt # ( $($inner:tt)* ) (+) $a1:tt $a2:tt $a3:tt) => {};
+
+    // A nonempty repetition with separator.
+    ($tokens:ident $b3:tt $b2:tt # (( $($inner:tt)* )) $sep:tt + $a3:tt) => {
+        $crate::quote_repetition!{quote_bind_into_iter $tokens [$sep] [] [empty_repetition] $($inner)*}
+    };
+    // ... and one step later.
+    ($tokens:ident $b3:tt # ( $($inner:tt)* ) ($sep:tt) + $a2:tt $a3:tt) => {};
+    // (Special cases for a `+` directly after a repetition of a different
+    // kind, or after `#(var)+`, which is an ordinary token rather than the
+    // repetition symbol of a repetition separated by `*`, `?` or `+`.)
+    ($tokens:ident # ( $($inner:tt)* ) * (+) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_token!{+ $tokens}
+    };
+    ($tokens:ident # ( $($inner:tt)* ) ? (+) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_token!{+ $tokens}
+    };
+    ($tokens:ident # ( $($inner:tt)* ) + (+) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_token!{+ $tokens}
+    };
+    // ... and one step later.
+    ($tokens:ident # ( $($inner:tt)* ) $sep:tt (+) $a1:tt $a2:tt $a3:tt) => {};
+
+    // An escaped `#[[ident]]`, which is emitted as a `#` followed by the ident
+    // rather than interpolated.
+    ($tokens:ident $b3:tt $b2:tt $b1:tt (#) [[$var:ident]] $a2:tt $a3:tt) => {
+        $crate::quote_token!{# $tokens}
+        $crate::quote_token!{$var $tokens}
+    };
+    // ... and one step later.
+    ($tokens:ident $b3:tt $b2:tt # ([[$var:ident]]) $a1:tt $a2:tt $a3:tt) => {};
+
+    // A let-binding, visible to the rest of the enclosing group. This comes
+    // after the repetition rules so that `#(let ...)*` remains a repetition.
+    ($tokens:ident $b3:tt $b2:tt # ((let $($stmt:tt)*)) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_expr!{[] [] let $($stmt)*}
+    };
+
+    // A concatenated identifier. Like a let-binding, this comes after the
+    // repetition rules so that `#(ident ...)*` remains a repetition.
+    ($tokens:ident $b3:tt $b2:tt # ((ident $($frags:tt)*)) $a1:tt $a2:tt $a3:tt) => {
+        $crate::ToTokens::to_tokens(&$crate::quote_ident!{$crate::__private::Option::None; $($frags)*}, &mut $tokens);
+    };
+
+    // A closure that writes directly into the output.
+    ($tokens:ident $b3:tt $b2:tt # ((| $param:ident $(: $ty:ty)? | $($body:tt)*)) $a1:tt $a2:tt $a3:tt) => {
+        $crate::__private::push_with(&mut $tokens, |$param $(: $ty)?| $($body)*);
+    };
+    ($tokens:ident $b3:tt $b2:tt # ((| $($param:pat_param),* | $($body:tt)*)) $a1:tt $a2:tt $a3:tt) => {
+        $crate::__private::push_with(&mut $tokens, |$($param),*| $($body)*);
+    };
+    ($tokens:ident $b3:tt $b2:tt # ((move | $param:ident $(: $ty:ty)? | $($body:tt)*)) $a1:tt $a2:tt $a3:tt) => {
+        $crate::__private::push_with(&mut $tokens, move |$param $(: $ty)?| $($body)*);
+    };
+    ($tokens:ident $b3:tt $b2:tt # ((move | $($param:pat_param),* | $($body:tt)*)) $a1:tt $a2:tt $a3:tt) => {
+        $crate::__private::push_with(&mut $tokens, move |$($param),*| $($body)*);
+    };
+    ($tokens:ident $b3:tt $b2:tt # (($(move)? | $($rest:tt)*)) $a1:tt $a2:tt $a3:tt) => {
+        $crate::__private::compile_error!(
+            "unsupported closure parameters in `#(|...| ...)`; write `|tokens| ...` or `|tokens: &mut TokenStream| ...`"
+        );
+    };
+
+    // Doc comments whose text refers to the variables listed before them. Like
+    // a let-binding, this comes after the repetition rules so that
+    // `#(doc ...)*` remains a repetition.
+    ($tokens:ident $b3:tt $b2:tt # ((doc $(# $var:ident)* $(# [doc = $lit:literal])+)) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_doc!{$tokens false [$($var)*] $($lit)*}
+    };
+    ($tokens:ident $b3:tt $b2:tt # ((doc $(# $var:ident)* $(# ! [doc = $lit:literal])+)) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_doc!{$tokens true [$($var)*] $($lit)*}
+    };
+    ($tokens:ident $b3:tt $b2:tt # ((doc $($rest:tt)*)) $a1:tt $a2:tt $a3:tt) => {
+        $crate::__private::compile_error!(
+            "expected `#var`s followed by doc comments in `#(doc ...)`, as in `#(doc #var /// ...)`"
+        );
+    };
+
+    // A `#` and a parenthesized group that are not an interpolation after all,
+    // and are emitted as ordinary tokens.
+    ($tokens:ident $b3:tt $b2:tt # (( $($inner:tt)* )) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_token!{# $tokens}
+        $crate::quote_token!{( $($inner)* ) $tokens}
+    };
+
     // A non-repetition interpolation.
     ($tokens:ident $b3:tt $b2:tt $b1:tt (#) $var:ident $a2:tt $a3:tt) => {
         $crate::ToTokens::to_tokens(&$var, &mut $tokens);
@@ -933,48 +2556,203 @@ macro_rules! quote_token_with_context {
     // ... and one step later.
     ($tokens:ident $b3:tt $b2:tt # ($var:ident) $a1:tt $a2:tt $a3:tt) => {};
 
-    // An ordinary token, not part of any interpolation.
+    // An interpolated expression.
+    ($tokens:ident $b3:tt $b2:tt $b1:tt (#) { $($expr:tt)* } $a2:tt $a3:tt) => {
+        $crate::ToTokens::to_tokens(&($crate::quote_expr!{[] [] $($expr)*}), &mut $tokens);
+    };
+    // ... and one step later.
+    ($tokens:ident $b3:tt $b2:tt # ({ $($expr:tt)* }) $a1:tt $a2:tt $a3:tt) => {};
+
+    // Any other token near a `#`, which is not part of an interpolation.
     ($tokens:ident $b3:tt $b2:tt $b1:tt ($curr:tt) $a1:tt $a2:tt $a3:tt) => {
         $crate::quote_token!{$curr $tokens}
     };
 }
 
 // See the explanation on quote_each_token, and on the individual rules of
-// quote_token_with_context.
+// quote_interpolation_with_context.
 #[macro_export]
 #[doc(hidden)]
-macro_rules! quote_token_with_context_spanned {
+macro_rules! quote_interpolation_with_context_spanned {
+    ($tokens:ident $span:ident # (if $($cond:tt)*) { $($then:tt)* } (#) (else) { $($else:tt)* } $a3:tt) => {{
+        let _cond = $crate::__private::quote_cond($crate::quote_expr!{[] [] $($cond)*});
+        if _cond {
+            $crate::quote_each_token_spanned!{$tokens $span $($then)*}
+        } else {
+            $crate::quote_each_token_spanned!{$tokens $span $($else)*}
+        }
+    }};
+    ($tokens:ident $span:ident # (if $($cond:tt)*) { $($then:tt)* } $curr:tt $a1:tt $a2:tt $a3:tt) => {
+        {
+            let _cond = $crate::__private::quote_cond($crate::quote_expr!{[] [] $($cond)*});
+            if _cond {
+                $crate::quote_each_token_spanned!{$tokens $span $($then)*}
+            }
+        }
+        $crate::quote_token_with_context_spanned!{$tokens $span @ @ @ $curr $a1 $a2 $a3}
+    };
+
     ($tokens:ident $span:ident $b3:tt $b2:tt $b1:tt @ $a1:tt $a2:tt $a3:tt) => {};
 
-    ($tokens:ident $span:ident $b3:tt $b2:tt $b1:tt (#) ( $($inner:tt)* ) * $a3:tt) => {{
-        use $crate::__private::ext::*;
-        let has_iter = $crate::__private::HasIterator::<false>;
-        $crate::pounded_var_names!{quote_bind_into_iter!(has_iter) () $($inner)*}
-        <_ as $crate::__private::CheckHasIterator<true>>::check(has_iter);
-        while true {
-            $crate::pounded_var_names!{quote_bind_next_or_break!() () $($inner)*}
-            $crate::quote_each_token_spanned!{$tokens $span $($inner)*}
+    ($tokens:ident $span:ident $b3:tt $b2:tt $b1:tt (#) (if $($cond:tt)*) { $($then:tt)* } $a3:tt) => {};
+    ($tokens:ident $span:ident $b3:tt $b2:tt # ((if $($cond:tt)*)) { $($then:tt)* } $a2:tt $a3:tt) => {};
+    ($tokens:ident $span:ident $b3:tt # (if $($cond:tt)*) ({ $($then:tt)* }) $a1:tt $a2:tt $a3:tt) => {};
+    ($tokens:ident $span:ident (if $($cond:tt)*) { $($then:tt)* } $b1:tt ($curr:tt) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_token_with_context_spanned!{$tokens $span @ @ $b1 ($curr) $a1 $a2 $a3}
+    };
+
+    ($tokens:ident $span:ident $b3:tt $b2:tt $b1:tt (#) (else) { $($else:tt)* } $a3:tt) => {
+        $crate::__private::compile_error!("`#(else)` must directly follow the body of an `#(if ...)`");
+    };
+    ($tokens:ident $span:ident $b3:tt $b2:tt # ((else)) { $($else:tt)* } $a2:tt $a3:tt) => {};
+    ($tokens:ident $span:ident $b3:tt # (else) ({ $($else:tt)* }) $a1:tt $a2:tt $a3:tt) => {};
+    ($tokens:ident $span:ident # (else) { $($else:tt)* } ($curr:tt) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_token_with_context_spanned!{$tokens $span @ @ @ ($curr) $a1 $a2 $a3}
+    };
+    ($tokens:ident $span:ident (else) { $($else:tt)* } $b1:tt ($curr:tt) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_token_with_context_spanned!{$tokens $span @ @ $b1 ($curr) $a1 $a2 $a3}
+    };
+
+    ($tokens:ident $span:ident $b3:tt $b2:tt $b1:tt (#) (match $($expr:tt)*) { $( $pat:pat $(if $guard:expr)? => { $($body:tt)* } $(,)? )* } $a3:tt) => {
+        match $crate::quote_expr!{[] [] $($expr)*} {
+            $(
+                $pat $(if $guard)? => {
+                    $crate::quote_each_token_spanned!{$tokens $span $($body)*}
+                }
+            )*
         }
+    };
+    ($tokens:ident $span:ident $b3:tt $b2:tt $b1:tt (#) (match $($expr:tt)*) { $($arms:tt)* } $a3:tt) => {
+        $crate::__private::compile_error!("each arm of `#(match ...)` must be written as `Pattern => { ... }`");
+    };
+    ($tokens:ident $span:ident $b3:tt $b2:tt # ((match $($expr:tt)*)) { $($arms:tt)* } $a2:tt $a3:tt) => {};
+    ($tokens:ident $span:ident $b3:tt # (match $($expr:tt)*) ({ $($arms:tt)* }) $a1:tt $a2:tt $a3:tt) => {};
+    ($tokens:ident $span:ident # (match $($expr:tt)*) { $($arms:tt)* } ($curr:tt) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_token_with_context_spanned!{$tokens $span @ @ @ ($curr) $a1 $a2 $a3}
+    };
+    ($tokens:ident $span:ident (match $($expr:tt)*) { $($arms:tt)* } $b1:tt ($curr:tt) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_token_with_context_spanned!{$tokens $span @ @ $b1 ($curr) $a1 $a2 $a3}
+    };
+
+    ($tokens:ident $span:ident $b3:tt $b2:tt $b1:tt (#) (span = $($region:tt)*) { $($body:tt)* } $a3:tt) => {{
+        let _span: $crate::__private::Span = $crate::__private::get_span($crate::quote_expr!{[] [] $($region)*}).__into_span();
+        $crate::quote_each_token_spanned!{$tokens _span $($body)*}
     }};
-    ($tokens:ident $span:ident $b3:tt $b2:tt # (( $($inner:tt)* )) * $a2:tt $a3:tt) => {};
+    ($tokens:ident $span:ident $b3:tt $b2:tt # ((span = $($region:tt)*)) { $($body:tt)* } $a2:tt $a3:tt) => {};
+    ($tokens:ident $span:ident $b3:tt # (span = $($region:tt)*) ({ $($body:tt)* }) $a1:tt $a2:tt $a3:tt) => {};
+    ($tokens:ident $span:ident # (span = $($region:tt)*) { $($body:tt)* } ($curr:tt) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_token_with_context_spanned!{$tokens $span @ @ @ ($curr) $a1 $a2 $a3}
+    };
+    ($tokens:ident $span:ident (span = $($region:tt)*) { $($body:tt)* } $b1:tt ($curr:tt) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_token_with_context_spanned!{$tokens $span @ @ $b1 ($curr) $a1 $a2 $a3}
+    };
+
+    ($tokens:ident $span:ident $b3:tt $b2:tt $b1:tt (#) ( $($inner:tt)* ) $a2:tt $a3:tt) => {};
+
+    ($tokens:ident $span:ident $b3:tt $b2:tt # (( $($inner:tt)* )) * $a2:tt $a3:tt) => {
+        $crate::quote_repetition_spanned!{quote_bind_into_iter $tokens $span [] [] [] $($inner)*}
+    };
     ($tokens:ident $span:ident $b3:tt # ( $($inner:tt)* ) (*) $a1:tt $a2:tt $a3:tt) => {};
 
-    ($tokens:ident $span:ident $b3:tt $b2:tt $b1:tt (#) ( $($inner:tt)* ) $sep:tt *) => {{
-        use $crate::__private::ext::*;
-        let mut _first = true;
-        let has_iter = $crate::__private::HasIterator::<false>;
-        $crate::pounded_var_names!{quote_bind_into_iter!(has_iter) () $($inner)*}
-        <_ as $crate::__private::CheckHasIterator<true>>::check(has_iter);
-        while true {
-            $crate::pounded_var_names!{quote_bind_next_or_break!() () $($inner)*}
-            if !_first {
-                $crate::quote_token_spanned!{$sep $tokens $span}
-            }
-            _first = false;
-            $crate::quote_each_token_spanned!{$tokens $span $($inner)*}
-        }
-    }};
-    ($tokens:ident $span:ident $b3:tt $b2:tt # (( $($inner:tt)* )) $sep:tt * $a3:tt) => {};
+    ($tokens:ident $span:ident $b3:tt $b2:tt # ((for $($for:tt)*)) { $($body:tt)* } * $a3:tt) => {
+        $crate::quote_for_spanned!{$tokens $span [] [$($for)*] $($body)*}
+    };
+    ($tokens:ident $span:ident $b3:tt $b2:tt # ((for $($for:tt)*)) { $($body:tt)* } # [ $($sep:tt)* ]) => {};
+    ($tokens:ident $span:ident $b3:tt $b2:tt # ((for $($for:tt)*)) { $($body:tt)* } $sep:tt *) => {
+        $crate::quote_for_spanned!{$tokens $span [$sep] [$($for)*] $($body)*}
+    };
+    ($tokens:ident $span:ident $b3:tt $b2:tt # ((for $($pat:tt)*)) { $($body:tt)* } $a2:tt $a3:tt) => {
+        $crate::__private::compile_error!("expected `*` or a separator and `*` after the body of `#(for ...)`");
+    };
+    ($tokens:ident $span:ident $b3:tt # (for $($for:tt)*) ({ $($body:tt)* }) # [ $($sep:tt)* ] *) => {
+        $crate::quote_for_spanned!{$tokens $span [$($sep)*] [$($for)*] $($body)*}
+    };
+    ($tokens:ident $span:ident $b3:tt # (for $($for:tt)*) ({ $($body:tt)* }) # [ $($sep:tt)* ] $a3:tt) => {
+        $crate::__private::compile_error!("expected `*` or a separator and `*` after the body of `#(for ...)`");
+    };
+    ($tokens:ident $span:ident $b3:tt # (for $($pat:tt)*) ({ $($body:tt)* }) $a1:tt $a2:tt $a3:tt) => {};
+    ($tokens:ident $span:ident # (for $($pat:tt)*) { $($body:tt)* } (*) * $a2:tt $a3:tt) => {
+        $crate::quote_token_spanned!{* $tokens $span}
+    };
+    ($tokens:ident $span:ident # (for $($pat:tt)*) { $($body:tt)* } ($sep:tt) $a1:tt $a2:tt $a3:tt) => {};
+    ($tokens:ident $span:ident (for $($pat:tt)*) { $($body:tt)* } $sep:tt (*) $a1:tt $a2:tt $a3:tt) => {};
+    ($tokens:ident $span:ident $b3:tt (for $($pat:tt)*) { $($body:tt)* } ($sep:tt) * $a2:tt $a3:tt) => {
+        $crate::quote_token_spanned!{$sep $tokens $span}
+        $crate::quote_token_spanned!{* $tokens $span}
+    };
+
+    ($tokens:ident $span:ident $b3:tt $b2:tt # (( $($inner:tt)* )) $sep:tt ? *) => {
+        $crate::quote_repetition_spanned!{quote_bind_into_iter $tokens $span [$sep] [$sep] [] $($inner)*}
+    };
+    ($tokens:ident $span:ident $b3:tt # ( $($inner:tt)* ) ($sep:tt) ? * $a3:tt) => {};
+    ($tokens:ident $span:ident # ( $($inner:tt)* ) $sep:tt (?) * $a2:tt $a3:tt) => {};
+    ($tokens:ident $span:ident ( $($inner:tt)* ) $sep:tt ? (*) $a1:tt $a2:tt $a3:tt) => {};
+    ($tokens:ident $span:ident $b3:tt ( $($inner:tt)* ) $sep:tt (?) * $a2:tt $a3:tt) => {
+        $crate::quote_token_spanned!{? $tokens $span}
+        $crate::quote_token_spanned!{* $tokens $span}
+    };
+
+    ($tokens:ident $span:ident $b3:tt $b2:tt # (( $($inner:tt)* )) # [ $($sep:tt)* ] *) => {};
+    ($tokens:ident $span:ident $b3:tt $b2:tt # (( $($inner:tt)* )) # [ $($sep:tt)* ] +) => {};
+    ($tokens:ident $span:ident $b3:tt $b2:tt # (( $($inner:tt)* )) # [ $($sep:tt)* ] ?) => {};
+    ($tokens:ident $span:ident $b3:tt # ( $($inner:tt)* ) (#) [ $($sep:tt)* ] * $a3:tt) => {};
+    ($tokens:ident $span:ident $b3:tt # ( $($inner:tt)* ) (#) [ $($sep:tt)* ] + $a3:tt) => {};
+    ($tokens:ident $span:ident $b3:tt # ( $($inner:tt)* ) (#) [ $($sep:tt)* ] ? *) => {};
+    ($tokens:ident $span:ident $b3:tt # ( $($inner:tt)* ) (#) [ $($sep:tt)* ] ? $a3:tt) => {
+        $crate::quote_interpolation_with_context_spanned!{$tokens $span @ @ # (( $($inner)* )) @ @ @}
+        $crate::quote_token_spanned!{# $tokens $span}
+    };
+    ($tokens:ident $span:ident # ( $($inner:tt)* ) # ([ $($sep:tt)* ]) ? * $a3:tt) => {
+        $crate::quote_repetition_spanned!{quote_bind_into_iter $tokens $span [$($sep)*] [$($sep)*] [] $($inner)*}
+    };
+    ($tokens:ident $span:ident # ( $($inner:tt)* ) # ([ $($sep:tt)* ]) * $a2:tt $a3:tt) => {
+        $crate::quote_repetition_spanned!{quote_bind_into_iter $tokens $span [$($sep)*] [] [] $($inner)*}
+    };
+    ($tokens:ident $span:ident # ( $($inner:tt)* ) # ([ $($sep:tt)* ]) + $a2:tt $a3:tt) => {
+        $crate::quote_repetition_spanned!{quote_bind_into_iter $tokens $span [$($sep)*] [] [empty_repetition] $($inner)*}
+    };
+    ($tokens:ident $span:ident (for $($pat:tt)*) { $($body:tt)* } # ([ $($sep:tt)* ]) * $a2:tt $a3:tt) => {};
+    ($tokens:ident $span:ident $b3:tt $b2:tt # ([ $($sep:tt)* ]) ? * $a3:tt) => {
+        $crate::quote_interpolation_with_context_spanned!{$tokens $span @ @ # ([ $($sep)* ]) @ @ @}
+        $crate::quote_token_spanned!{? $tokens $span}
+        $crate::quote_token_spanned!{* $tokens $span}
+    };
+    ($tokens:ident $span:ident $b3:tt $b2:tt # ([ $($sep:tt)* ]) * $a2:tt $a3:tt) => {
+        $crate::quote_interpolation_with_context_spanned!{$tokens $span @ @ # ([ $($sep)* ]) @ @ @}
+        $crate::quote_token_spanned!{* $tokens $span}
+    };
+    ($tokens:ident $span:ident $b3:tt $b2:tt # ([ $($sep:tt)* ]) + $a2:tt $a3:tt) => {
+        $crate::quote_interpolation_with_context_spanned!{$tokens $span @ @ # ([ $($sep)* ]) @ @ @}
+        $crate::quote_token_spanned!{+ $tokens $span}
+    };
+    ($tokens:ident $span:ident $b3:tt # [ $($sep:tt)* ] (*) $a1:tt $a2:tt $a3:tt) => {};
+    ($tokens:ident $span:ident $b3:tt # [ $($sep:tt)* ] (+) $a1:tt $a2:tt $a3:tt) => {};
+    ($tokens:ident $span:ident $b3:tt # [ $($sep:tt)* ] (?) * $a2:tt $a3:tt) => {};
+    ($tokens:ident $span:ident # [ $($sep:tt)* ] ? (*) $a1:tt $a2:tt $a3:tt) => {};
+
+    ($tokens:ident $span:ident $b3:tt $b2:tt # (( $($inner:tt)* )) # $sep:ident *) => {
+        $crate::quote_repetition_spanned!{quote_bind_into_iter $tokens $span [# $sep] [] [] $($inner)*}
+    };
+    ($tokens:ident $span:ident $b3:tt # ( $($inner:tt)* ) (#) $sep:ident * $a3:tt) => {};
+    ($tokens:ident $span:ident # ( $($inner:tt)* ) # ($sep:ident) * $a2:tt $a3:tt) => {};
+    ($tokens:ident $span:ident ( $($inner:tt)* ) # $sep:ident (*) $a1:tt $a2:tt $a3:tt) => {};
+    ($tokens:ident $span:ident $b3:tt ( $($inner:tt)* ) # ($sep:ident) * $a2:tt $a3:tt) => {
+        $crate::quote_token_spanned!{* $tokens $span}
+    };
+
+    ($tokens:ident $span:ident $b3:tt $b2:tt # (( $($inner:tt)* )) # $sep:ident +) => {
+        $crate::quote_repetition_spanned!{quote_bind_into_iter $tokens $span [# $sep] [] [empty_repetition] $($inner)*}
+    };
+    ($tokens:ident $span:ident $b3:tt # ( $($inner:tt)* ) (#) $sep:ident + $a3:tt) => {};
+    ($tokens:ident $span:ident # ( $($inner:tt)* ) # ($sep:ident) + $a2:tt $a3:tt) => {};
+    ($tokens:ident $span:ident ( $($inner:tt)* ) # $sep:ident (+) $a1:tt $a2:tt $a3:tt) => {};
+    ($tokens:ident $span:ident $b3:tt ( $($inner:tt)* ) # ($sep:ident) + $a2:tt $a3:tt) => {
+        $crate::quote_token_spanned!{+ $tokens $span}
+    };
+
+    ($tokens:ident $span:ident $b3:tt $b2:tt # (( $($inner:tt)* )) $sep:tt * $a3:tt) => {
+        $crate::quote_repetition_spanned!{quote_bind_into_iter $tokens $span [$sep] [] [] $($inner)*}
+    };
     ($tokens:ident $span:ident $b3:tt # ( $($inner:tt)* ) ($sep:tt) * $a2:tt $a3:tt) => {};
     ($tokens:ident $span:ident # ( $($inner:tt)* ) * (*) $a1:tt $a2:tt $a3:tt) => {
         // https://github.com/dtolnay/quote/issues/130
@@ -982,11 +2760,90 @@ macro_rules! quote_token_with_context_spanned {
     };
     ($tokens:ident $span:ident # ( $($inner:tt)* ) $sep:tt (*) $a1:tt $a2:tt $a3:tt) => {};
 
+    ($tokens:ident $span:ident $b3:tt $b2:tt # (( $($inner:tt)* )) ? $a2:tt $a3:tt) => {
+        $crate::quote_repetition_spanned!{quote_bind_into_opt $tokens $span [] [] [] $($inner)*}
+    };
+    ($tokens:ident $span:ident $b3:tt # ( $($inner:tt)* ) (?) $a1:tt $a2:tt $a3:tt) => {};
+
+    ($tokens:ident $span:ident $b3:tt $b2:tt # (( $($inner:tt)* )) + $a2:tt $a3:tt) => {
+        $crate::quote_repetition_spanned!{quote_bind_into_iter $tokens $span [] [] [empty_repetition] $($inner)*}
+    };
+    ($tokens:ident $span:ident $b3:tt # ( $($inner:tt)* ) (+) $a1:tt $a2:tt $a3:tt) => {};
+
+    ($tokens:ident $span:ident $b3:tt $b2:tt # (( $($inner:tt)* )) $sep:tt + $a3:tt) => {
+        $crate::quote_repetition_spanned!{quote_bind_into_iter $tokens $span [$sep] [] [empty_repetition] $($inner)*}
+    };
+    ($tokens:ident $span:ident $b3:tt # ( $($inner:tt)* ) ($sep:tt) + $a2:tt $a3:tt) => {};
+    ($tokens:ident $span:ident # ( $($inner:tt)* ) * (+) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_token_spanned!{+ $tokens $span}
+    };
+    ($tokens:ident $span:ident # ( $($inner:tt)* ) ? (+) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_token_spanned!{+ $tokens $span}
+    };
+    ($tokens:ident $span:ident # ( $($inner:tt)* ) + (+) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_token_spanned!{+ $tokens $span}
+    };
+    ($tokens:ident $span:ident # ( $($inner:tt)* ) $sep:tt (+) $a1:tt $a2:tt $a3:tt) => {};
+
+    ($tokens:ident $span:ident $b3:tt $b2:tt $b1:tt (#) [[$var:ident]] $a2:tt $a3:tt) => {
+        $crate::quote_token_spanned!{# $tokens $span}
+        $crate::quote_token_spanned!{$var $tokens $span}
+    };
+    ($tokens:ident $span:ident $b3:tt $b2:tt # ([[$var:ident]]) $a1:tt $a2:tt $a3:tt) => {};
+
+    ($tokens:ident $span:ident $b3:tt $b2:tt # ((let $($stmt:tt)*)) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_expr!{[] [] let $($stmt)*}
+    };
+
+    ($tokens:ident $span:ident $b3:tt $b2:tt # ((ident $($frags:tt)*)) $a1:tt $a2:tt $a3:tt) => {
+        $crate::ToTokens::to_tokens(&$crate::quote_ident!{$crate::__private::Option::Some($span); $($frags)*}, &mut $tokens);
+    };
+
+    ($tokens:ident $span:ident $b3:tt $b2:tt # ((| $param:ident $(: $ty:ty)? | $($body:tt)*)) $a1:tt $a2:tt $a3:tt) => {
+        $crate::__private::push_with(&mut $tokens, |$param $(: $ty)?| $($body)*);
+    };
+    ($tokens:ident $span:ident $b3:tt $b2:tt # ((| $($param:pat_param),* | $($body:tt)*)) $a1:tt $a2:tt $a3:tt) => {
+        $crate::__private::push_with(&mut $tokens, |$($param),*| $($body)*);
+    };
+    ($tokens:ident $span:ident $b3:tt $b2:tt # ((move | $param:ident $(: $ty:ty)? | $($body:tt)*)) $a1:tt $a2:tt $a3:tt) => {
+        $crate::__private::push_with(&mut $tokens, move |$param $(: $ty)?| $($body)*);
+    };
+    ($tokens:ident $span:ident $b3:tt $b2:tt # ((move | $($param:pat_param),* | $($body:tt)*)) $a1:tt $a2:tt $a3:tt) => {
+        $crate::__private::push_with(&mut $tokens, move |$($param),*| $($body)*);
+    };
+    ($tokens:ident $span:ident $b3:tt $b2:tt # (($(move)? | $($rest:tt)*)) $a1:tt $a2:tt $a3:tt) => {
+        $crate::__private::compile_error!(
+            "unsupported closure parameters in `#(|...| ...)`; write `|tokens| ...` or `|tokens: &mut TokenStream| ...`"
+        );
+    };
+
+    ($tokens:ident $span:ident $b3:tt $b2:tt # ((doc $(# $var:ident)* $(# [doc = $lit:literal])+)) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_doc!{$tokens $span false [$($var)*] $($lit)*}
+    };
+    ($tokens:ident $span:ident $b3:tt $b2:tt # ((doc $(# $var:ident)* $(# ! [doc = $lit:literal])+)) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_doc!{$tokens $span true [$($var)*] $($lit)*}
+    };
+    ($tokens:ident $span:ident $b3:tt $b2:tt # ((doc $($rest:tt)*)) $a1:tt $a2:tt $a3:tt) => {
+        $crate::__private::compile_error!(
+            "expected `#var`s followed by doc comments in `#(doc ...)`, as in `#(doc #var /// ...)`"
+        );
+    };
+
+    ($tokens:ident $span:ident $b3:tt $b2:tt # (( $($inner:tt)* )) $a1:tt $a2:tt $a3:tt) => {
+        $crate::quote_token_spanned!{# $tokens $span}
+        $crate::quote_token_spanned!{( $($inner)* ) $tokens $span}
+    };
+
     ($tokens:ident $span:ident $b3:tt $b2:tt $b1:tt (#) $var:ident $a2:tt $a3:tt) => {
         $crate::ToTokens::to_tokens(&$var, &mut $tokens);
     };
     ($tokens:ident $span:ident $b3:tt $b2:tt # ($var:ident) $a1:tt $a2:tt $a3:tt) => {};
 
+    ($tokens:ident $span:ident $b3:tt $b2:tt $b1:tt (#) { $($expr:tt)* } $a2:tt $a3:tt) => {
+        $crate::ToTokens::to_tokens(&($crate::quote_expr!{[] [] $($expr)*}), &mut $tokens);
+    };
+    ($tokens:ident $span:ident $b3:tt $b2:tt # ({ $($expr:tt)* }) $a1:tt $a2:tt $a3:tt) => {};
+
     ($tokens:ident $span:ident $b3:tt $b2:tt $b1:tt ($curr:tt) $a1:tt $a2:tt $a3:tt) => {
         $crate::quote_token_spanned!{$curr $tokens $span}
     };
@@ -0,0 +1,256 @@
+use alloc::collections::binary_heap::{self, BinaryHeap};
+use alloc::collections::btree_map::{self, BTreeMap};
+use alloc::collections::btree_set::{self, BTreeSet};
+use alloc::collections::linked_list::{self, LinkedList};
+use alloc::collections::vec_deque::{self, VecDeque};
+#[cfg(feature = "std")]
+use alloc::vec;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use core::iter;
+use core::slice;
+#[cfg(feature = "std")]
+use std::collections::hash_map::{self, HashMap};
+#[cfg(feature = "std")]
+use std::collections::hash_set::{self, HashSet};
+
+/// Types that can be repeated over by `#(...)*` in [`quote!`].
+///
+/// [`quote!`]: crate::quote
+///
+/// A variable interpolated inside a repetition is iterated through by
+/// reference, once per expansion of the template, so the collection is still
+/// available afterward. Implement this trait for your own collection type to
+/// allow it in a repetition in the same way as a `Vec` or `BTreeSet`.
+///
+/// ```
+/// use quote::{quote, QuoteRepeat};
+/// use std::slice;
+///
+/// struct Fields {
+///     names: Vec<&'static str>,
+/// }
+///
+/// impl QuoteRepeat for Fields {
+///     type Iter<'q> = slice::Iter<'q, &'static str>;
+///
+///     fn quote_repeat(&self) -> Self::Iter<'_> {
+///         self.names.iter()
+///     }
+/// }
+///
+/// let fields = Fields {
+///     names: vec!["x", "y"],
+/// };
+/// let tokens = quote!(#(#fields),*);
+/// assert_eq!(tokens.to_string(), "\"x\" , \"y\"");
+/// ```
+///
+/// A type that implements neither this trait nor `Iterator` is rejected at
+/// compile time when used to drive a repetition.
+///
+/// `Option` deliberately does not implement this trait. An `Option` of a
+/// `ToTokens` type is itself `ToTokens`, and inside of `#(...)*` it is
+/// interpolated as its contents, or nothing, in every iteration alongside the
+/// collections that drive the repetition. Repeating over it instead would
+/// change the meaning of existing templates. An `Option` drives the optional
+/// repetition `#(...)?` instead.
+pub trait QuoteRepeat {
+    /// The iterator over borrowed elements of the collection.
+    type Iter<'q>: Iterator
+    where
+        Self: 'q;
+
+    /// Returns an iterator over the elements to repeat over.
+    fn quote_repeat(&self) -> Self::Iter<'_>;
+}
+
+impl<T: QuoteRepeat + ?Sized> QuoteRepeat for &T {
+    type Iter<'q>
+        = T::Iter<'q>
+    where
+        Self: 'q;
+
+    fn quote_repeat(&self) -> Self::Iter<'_> {
+        T::quote_repeat(*self)
+    }
+}
+
+impl<T: QuoteRepeat + ?Sized> QuoteRepeat for &mut T {
+    type Iter<'q>
+        = T::Iter<'q>
+    where
+        Self: 'q;
+
+    fn quote_repeat(&self) -> Self::Iter<'_> {
+        T::quote_repeat(*self)
+    }
+}
+
+impl<T> QuoteRepeat for [T] {
+    type Iter<'q>
+        = slice::Iter<'q, T>
+    where
+        Self: 'q;
+
+    fn quote_repeat(&self) -> Self::Iter<'_> {
+        self.iter()
+    }
+}
+
+impl<T, const N: usize> QuoteRepeat for [T; N] {
+    type Iter<'q>
+        = slice::Iter<'q, T>
+    where
+        Self: 'q;
+
+    fn quote_repeat(&self) -> Self::Iter<'_> {
+        self.iter()
+    }
+}
+
+impl<T> QuoteRepeat for Vec<T> {
+    type Iter<'q>
+        = slice::Iter<'q, T>
+    where
+        Self: 'q;
+
+    fn quote_repeat(&self) -> Self::Iter<'_> {
+        self.iter()
+    }
+}
+
+impl<T> QuoteRepeat for BTreeSet<T> {
+    type Iter<'q>
+        = btree_set::Iter<'q, T>
+    where
+        Self: 'q;
+
+    fn quote_repeat(&self) -> Self::Iter<'_> {
+        self.iter()
+    }
+}
+
+impl<T> QuoteRepeat for VecDeque<T> {
+    type Iter<'q>
+        = vec_deque::Iter<'q, T>
+    where
+        Self: 'q;
+
+    fn quote_repeat(&self) -> Self::Iter<'_> {
+        self.iter()
+    }
+}
+
+impl<T> QuoteRepeat for LinkedList<T> {
+    type Iter<'q>
+        = linked_list::Iter<'q, T>
+    where
+        Self: 'q;
+
+    fn quote_repeat(&self) -> Self::Iter<'_> {
+        self.iter()
+    }
+}
+
+impl<T> QuoteRepeat for BinaryHeap<T> {
+    type Iter<'q>
+        = binary_heap::Iter<'q, T>
+    where
+        Self: 'q;
+
+    fn quote_repeat(&self) -> Self::Iter<'_> {
+        self.iter()
+    }
+}
+
+/// Maps are repeated over their values, in the order of their keys.
+impl<K, V> QuoteRepeat for BTreeMap<K, V> {
+    type Iter<'q>
+        = btree_map::Values<'q, K, V>
+    where
+        Self: 'q;
+
+    fn quote_repeat(&self) -> Self::Iter<'_> {
+        self.values()
+    }
+}
+
+/// Iterates in the unspecified order of the `HashSet`. See `Sorted` for a
+/// deterministic order.
+#[cfg(feature = "std")]
+impl<T, S> QuoteRepeat for HashSet<T, S> {
+    type Iter<'q>
+        = hash_set::Iter<'q, T>
+    where
+        Self: 'q;
+
+    fn quote_repeat(&self) -> Self::Iter<'_> {
+        self.iter()
+    }
+}
+
+/// Iterates over values in the unspecified order of the `HashMap`. See
+/// `Sorted` for a deterministic order.
+#[cfg(feature = "std")]
+impl<K, V, S> QuoteRepeat for HashMap<K, V, S> {
+    type Iter<'q>
+        = hash_map::Values<'q, K, V>
+    where
+        Self: 'q;
+
+    fn quote_repeat(&self) -> Self::Iter<'_> {
+        self.values()
+    }
+}
+
+/// Repeats over a hash-based collection in sorted order.
+///
+/// A `HashMap` or `HashSet` interpolated in a repetition is iterated in
+/// whatever order the collection stores its elements, which can differ from one
+/// build to the next. Wrapping it in `Sorted` iterates a `HashSet` in the order
+/// of its elements and a `HashMap` in the order of its keys, so that the
+/// generated code is reproducible.
+///
+/// ```
+/// use quote::{quote, Sorted};
+/// use std::collections::HashMap;
+///
+/// let mut defaults = HashMap::new();
+/// defaults.insert("b", quote!(false));
+/// defaults.insert("a", quote!(0));
+///
+/// let values = Sorted(&defaults);
+/// let tokens = quote!(#(#values),*);
+/// assert_eq!(tokens.to_string(), "0 , false");
+/// ```
+#[cfg(feature = "std")]
+pub struct Sorted<'a, C: ?Sized>(pub &'a C);
+
+#[cfg(feature = "std")]
+impl<'a, T: Ord, S> QuoteRepeat for Sorted<'a, HashSet<T, S>> {
+    type Iter<'q>
+        = vec::IntoIter<&'q T>
+    where
+        Self: 'q;
+
+    fn quote_repeat(&self) -> Self::Iter<'_> {
+        let mut elements: Vec<&T> = self.0.iter().collect();
+        elements.sort_unstable();
+        elements.into_iter()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a, K: Ord, V, S> QuoteRepeat for Sorted<'a, HashMap<K, V, S>> {
+    type Iter<'q>
+        = iter::Map<vec::IntoIter<(&'q K, &'q V)>, fn((&'q K, &'q V)) -> &'q V>
+    where
+        Self: 'q;
+
+    fn quote_repeat(&self) -> Self::Iter<'_> {
+        let mut entries: Vec<(&K, &V)> = self.0.iter().collect();
+        entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        entries.into_iter().map(|(_k, v)| v)
+    }
+}
@@ -1,13 +1,24 @@
 use self::get_span::{GetSpan, GetSpanBase, GetSpanInner};
-use crate::{IdentFragment, ToTokens, TokenStreamExt};
-use core::fmt;
+use crate::{IdentFragment, QuoteRepeat, ToTokens, TokenStreamExt};
+use alloc::string::String;
+#[cfg(feature = "checked")]
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::fmt::{self, Write as _};
 use core::iter;
-use core::ops::BitOr;
-use proc_macro2::{Group, Ident, Punct, Spacing, TokenTree};
+use core::marker::PhantomData;
+use core::ops::{BitOr, Deref};
+use proc_macro2::{Group, Ident, Literal, Punct, Spacing, TokenTree};
 
 #[doc(hidden)]
 pub use alloc::format;
 #[doc(hidden)]
+pub use core::assert;
+#[doc(hidden)]
+pub use core::compile_error;
+#[doc(hidden)]
+pub use core::concat;
+#[doc(hidden)]
 pub use core::option::Option;
 #[doc(hidden)]
 pub use core::stringify;
@@ -72,11 +83,11 @@ impl CheckHasIterator<true> for HasIterator<true> {}
 /// the returned value should be idempotent.
 #[doc(hidden)]
 pub mod ext {
-    use super::{HasIterator, RepInterp};
-    use crate::ToTokens;
-    use alloc::collections::btree_set::{self, BTreeSet};
-    use alloc::vec::Vec;
-    use core::slice;
+    #[cfg(feature = "checked")]
+    use super::RepLen;
+    use super::{CheckNotIterator, HasIterator, RepInterp, RepNotOptional, RepOption};
+    use crate::{QuoteRepeat, ToTokens};
+    use core::{iter, option};
 
     /// Extension trait providing the `quote_into_iter` method on iterators.
     #[doc(hidden)]
@@ -84,6 +95,21 @@ pub mod ext {
         fn quote_into_iter(self) -> (Self, HasIterator<true>) {
             (self, HasIterator::<true>)
         }
+
+        /// Used by the `checked` feature to measure the elements remaining
+        /// after the repetition stopped. The iterator may be unbounded, like
+        /// `iter::repeat`, so it is not drained. At most one more element is
+        /// taken and the size hint gives the rest if it is exact.
+        #[cfg(feature = "checked")]
+        fn quote_remaining(&mut self) -> Option<RepLen> {
+            if self.next().is_none() {
+                return Some(RepLen::Exact(0));
+            }
+            Some(match self.size_hint() {
+                (lower, Some(upper)) if lower == upper => RepLen::Exact(lower.saturating_add(1)),
+                _ => RepLen::AtLeast(1),
+            })
+        }
     }
 
     impl<T: Iterator> RepIteratorExt for T {}
@@ -103,12 +129,19 @@ pub mod ext {
         fn quote_into_iter(&self) -> (&Self, HasIterator<false>) {
             (self, HasIterator::<false>)
         }
+
+        /// Non-iterable values have no length to check.
+        #[cfg(feature = "checked")]
+        fn quote_remaining(&self) -> Option<RepLen> {
+            None
+        }
     }
 
     impl<T: ToTokens + ?Sized> RepToTokensExt for T {}
 
     /// Extension trait providing the `quote_into_iter` method for types that
-    /// can be referenced as an iterator.
+    /// can be referenced as an iterator, which are those implementing the
+    /// public `QuoteRepeat` trait.
     #[doc(hidden)]
     pub trait RepAsIteratorExt<'q> {
         type Iter: Iterator;
@@ -116,63 +149,135 @@ pub mod ext {
         fn quote_into_iter(&'q self) -> (Self::Iter, HasIterator<true>);
     }
 
-    impl<'q, T: RepAsIteratorExt<'q> + ?Sized> RepAsIteratorExt<'q> for &T {
-        type Iter = T::Iter;
+    impl<'q, T: QuoteRepeat + ?Sized + 'q> RepAsIteratorExt<'q> for T {
+        type Iter = T::Iter<'q>;
 
         fn quote_into_iter(&'q self) -> (Self::Iter, HasIterator<true>) {
-            <T as RepAsIteratorExt>::quote_into_iter(*self)
+            (self.quote_repeat(), HasIterator::<true>)
         }
     }
 
-    impl<'q, T: RepAsIteratorExt<'q> + ?Sized> RepAsIteratorExt<'q> for &mut T {
-        type Iter = T::Iter;
+    /// Extension trait providing the `quote_into_opt` method for `Option`,
+    /// used by `#(...)?` instead of `quote_into_iter`. An `Option` cannot be
+    /// given a `quote_into_iter` of its own because it is also `ToTokens`.
+    ///
+    /// This is implemented on `RepOption<&T>` and called on `&RepOption<&T>`,
+    /// so that it takes priority over `RepOptionToTokensExt` which is
+    /// implemented on `&RepOption<&T>`.
+    #[doc(hidden)]
+    pub trait RepOptionExt {
+        type Iter: Iterator;
 
-        fn quote_into_iter(&'q self) -> (Self::Iter, HasIterator<true>) {
-            <T as RepAsIteratorExt>::quote_into_iter(*self)
+        fn quote_into_opt(&self) -> (Self::Iter, HasIterator<true>);
+    }
+
+    impl<'q, T: 'q> RepOptionExt for RepOption<&'q Option<T>> {
+        type Iter = option::Iter<'q, T>;
+
+        fn quote_into_opt(&self) -> (Self::Iter, HasIterator<true>) {
+            (self.0.iter(), HasIterator::<true>)
         }
     }
 
-    impl<'q, T: 'q> RepAsIteratorExt<'q> for [T] {
-        type Iter = slice::Iter<'q, T>;
+    impl<'q, T: 'q> RepOptionExt for RepOption<&'q &Option<T>> {
+        type Iter = option::Iter<'q, T>;
 
-        fn quote_into_iter(&'q self) -> (Self::Iter, HasIterator<true>) {
-            (self.iter(), HasIterator::<true>)
+        fn quote_into_opt(&self) -> (Self::Iter, HasIterator<true>) {
+            (self.0.iter(), HasIterator::<true>)
         }
     }
 
-    impl<'q, T: 'q, const N: usize> RepAsIteratorExt<'q> for [T; N] {
-        type Iter = slice::Iter<'q, T>;
+    impl<'q, T: 'q> RepOptionExt for RepOption<&'q RepInterp<&Option<T>>> {
+        type Iter = option::Iter<'q, T>;
 
-        fn quote_into_iter(&'q self) -> (Self::Iter, HasIterator<true>) {
-            (self.iter(), HasIterator::<true>)
+        fn quote_into_opt(&self) -> (Self::Iter, HasIterator<true>) {
+            (self.0 .0.iter(), HasIterator::<true>)
         }
     }
 
-    impl<'q, T: 'q> RepAsIteratorExt<'q> for Vec<T> {
-        type Iter = slice::Iter<'q, T>;
+    // A variable that occurs more than once in the repetition has already been
+    // turned into an iterator by its previous occurrence.
+    impl<'q, 'a, T> RepOptionExt for RepOption<&'q option::Iter<'a, T>> {
+        type Iter = option::Iter<'a, T>;
 
-        fn quote_into_iter(&'q self) -> (Self::Iter, HasIterator<true>) {
-            (self.iter(), HasIterator::<true>)
+        fn quote_into_opt(&self) -> (Self::Iter, HasIterator<true>) {
+            (self.0.clone(), HasIterator::<true>)
         }
     }
 
-    impl<'q, T: 'q> RepAsIteratorExt<'q> for BTreeSet<T> {
-        type Iter = btree_set::Iter<'q, T>;
+    /// Extension trait providing the `quote_into_opt` method for non-optional
+    /// types. These types interpolate the same value in each iteration of the
+    /// repetition, as with `RepToTokensExt`.
+    #[doc(hidden)]
+    pub trait RepOptionToTokensExt<'q> {
+        type Value: ?Sized + 'q;
+
+        fn quote_into_opt(&self) -> (&'q Self::Value, HasIterator<false>)
+        where
+            Self::Value: CheckNotIterator;
+    }
+
+    impl<'q, T: CheckNotIterator + ?Sized> RepOptionToTokensExt<'q> for &RepOption<&'q T> {
+        type Value = T;
 
-        fn quote_into_iter(&'q self) -> (Self::Iter, HasIterator<true>) {
-            (self.iter(), HasIterator::<true>)
+        fn quote_into_opt(&self) -> (&'q T, HasIterator<false>) {
+            (self.0, HasIterator::<false>)
         }
     }
 
-    impl<'q, T: RepAsIteratorExt<'q>> RepAsIteratorExt<'q> for RepInterp<T> {
-        type Iter = T::Iter;
+    /// Extension trait providing the `quote_into_opt` method for all other
+    /// types, which is lower priority than `RepOptionToTokensExt` because it
+    /// takes `&mut self`. Its bound is never satisfied, and reports the one
+    /// error for the variable. The placeholder it binds instead of the value
+    /// keeps the rest of the repetition from reporting more.
+    #[doc(hidden)]
+    pub trait RepOptionNotToTokensExt {
+        type Value: ?Sized;
 
-        fn quote_into_iter(&'q self) -> (Self::Iter, HasIterator<true>) {
-            self.0.quote_into_iter()
+        fn quote_into_opt(&mut self) -> (iter::Empty<RepNotOptional>, HasIterator<true>)
+        where
+            Self::Value: CheckNotIterator;
+    }
+
+    impl<'q, T: ?Sized> RepOptionNotToTokensExt for &RepOption<&'q T> {
+        type Value = T;
+
+        fn quote_into_opt(&mut self) -> (iter::Empty<RepNotOptional>, HasIterator<true>)
+        where
+            T: CheckNotIterator,
+        {
+            (iter::empty(), HasIterator::<true>)
         }
     }
 }
 
+// Wrapper used by `#(...)?` to give `Option` precedence over other types that
+// implement `ToTokens` when binding the variables of the repetition.
+#[doc(hidden)]
+pub struct RepOption<T>(pub T);
+
+#[doc(hidden)]
+#[cfg_attr(
+    not(no_diagnostic_namespace),
+    diagnostic::on_unimplemented(
+        message = "`{Self}` cannot be interpolated inside of an optional repetition",
+        label = "only `Option` values control `#(...)?`, other values must implement `ToTokens`",
+        note = "use `#(...)*` to repeat over an iterator"
+    )
+)]
+pub trait CheckNotIterator {}
+
+impl<T: ToTokens + ?Sized> CheckNotIterator for T {}
+
+// Bound in place of a variable that cannot be interpolated inside of `#(...)?`,
+// after the error has been reported.
+#[doc(hidden)]
+pub struct RepNotOptional;
+
+impl ToTokens for RepNotOptional {
+    fn to_tokens(&self, _tokens: &mut TokenStream) {}
+}
+
 // Helper type used within interpolations to allow for repeated binding names.
 // Implements the relevant traits, and exports a dummy `next()` method.
 #[derive(Copy, Clone)]
@@ -189,6 +294,16 @@ impl<T> RepInterp<T> {
     }
 }
 
+// Lets an interpolated expression `#{...}` inside of a repetition use the
+// current element in place of the name that is bound to it.
+impl<T> Deref for RepInterp<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
 impl<T: Iterator> Iterator for RepInterp<T> {
     type Item = T::Item;
 
@@ -197,12 +312,245 @@ impl<T: Iterator> Iterator for RepInterp<T> {
     }
 }
 
+impl<T: QuoteRepeat> QuoteRepeat for RepInterp<T> {
+    type Iter<'a>
+        = T::Iter<'a>
+    where
+        Self: 'a;
+
+    fn quote_repeat(&self) -> Self::Iter<'_> {
+        self.0.quote_repeat()
+    }
+}
+
 impl<T: ToTokens> ToTokens for RepInterp<T> {
     fn to_tokens(&self, tokens: &mut TokenStream) {
         self.0.to_tokens(tokens);
     }
 }
 
+impl<T: fmt::Display> fmt::Display for RepInterp<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl<T: IdentFragment> IdentFragment for RepInterp<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        IdentFragment::fmt(&self.0, f)
+    }
+
+    fn span(&self) -> Option<Span> {
+        self.0.span()
+    }
+}
+
+// Stands in for a name bound by a nested `#(let pat = expr;)` while the
+// variables of an enclosing repetition are bound, because the tokens after it
+// refer to the value bound in each iteration instead. Like a value that is not
+// an iterator, it repeats itself in every iteration, but it cannot be
+// interpolated: at that point, the name was meant to refer to a variable from
+// outside of the repetition, which the placeholder has shadowed.
+#[doc(hidden)]
+#[derive(Default)]
+pub struct RepBinding<T = ()>(PhantomData<T>);
+
+impl RepBinding {
+    pub fn quote_into_iter(&self) -> (&Self, HasIterator<false>) {
+        (self, HasIterator::<false>)
+    }
+
+    pub fn next(&self) -> Option<&Self> {
+        Some(self)
+    }
+
+    #[cfg(feature = "checked")]
+    pub fn quote_remaining(&self) -> Option<RepLen> {
+        None
+    }
+}
+
+impl<T: CheckLetScope> ToTokens for RepBinding<T> {
+    fn to_tokens(&self, _tokens: &mut TokenStream) {}
+}
+
+impl CheckNotIterator for RepBinding {}
+
+#[doc(hidden)]
+#[cfg_attr(
+    not(no_diagnostic_namespace),
+    diagnostic::on_unimplemented(
+        message = "a name bound by `#(let ...)` inside of a repetition cannot also refer to a variable from outside of it",
+        label = "bound by a `#(let ...)` elsewhere in this repetition",
+        note = "give the `#(let ...)` binding a different name"
+    )
+)]
+pub trait CheckLetScope {}
+
+// Called by `#(...)+` when none of its iterators produced an item. The
+// location reported in the panic message is that of the macro invocation.
+#[doc(hidden)]
+#[cold]
+#[track_caller]
+pub fn empty_repetition() -> ! {
+    panic!("repetition `#(...)+` requires at least one element but the iterators interpolated inside of it were empty");
+}
+
+// The condition of an `#(if ...)`. Inside of a repetition it is usually written
+// `*#var`, which for a repetition over borrowed elements is a `&bool`.
+#[doc(hidden)]
+pub fn quote_cond<B: Borrow<bool>>(cond: B) -> bool {
+    *cond.borrow()
+}
+
+// Lengths of the iterators zipped by one repetition, for the `checked` feature.
+// The loop records which iterator ran out first, and afterward each iterator
+// is visited in the same order as it was advanced inside the loop, so those
+// before the one that ran out have had one more element taken from them.
+#[cfg(feature = "checked")]
+#[doc(hidden)]
+pub struct RepCheck {
+    ended: Option<&'static str>,
+    iterations: usize,
+    past_ended: bool,
+    lens: Vec<(&'static str, RepLen)>,
+}
+
+// Without the `checked` feature only the loop's call to `ended` remains.
+#[cfg(not(feature = "checked"))]
+#[doc(hidden)]
+pub struct RepCheck;
+
+// Length of one of the iterators checked by RepCheck, or a lower bound of it
+// if the iterator did not report its exact size.
+#[cfg(feature = "checked")]
+#[doc(hidden)]
+#[derive(Copy, Clone, PartialEq)]
+pub enum RepLen {
+    Exact(usize),
+    AtLeast(usize),
+}
+
+#[cfg(feature = "checked")]
+impl RepLen {
+    fn offset(self, taken: usize) -> Self {
+        match self {
+            RepLen::Exact(len) => RepLen::Exact(taken.saturating_add(len)),
+            RepLen::AtLeast(len) => RepLen::AtLeast(taken.saturating_add(len)),
+        }
+    }
+}
+
+#[cfg(feature = "checked")]
+impl fmt::Display for RepLen {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RepLen::Exact(len) => write!(f, "length {}", len),
+            RepLen::AtLeast(len) => write!(f, "length at least {}", len),
+        }
+    }
+}
+
+#[cfg(feature = "checked")]
+impl RepCheck {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        RepCheck {
+            ended: None,
+            iterations: 0,
+            past_ended: false,
+            lens: Vec::new(),
+        }
+    }
+
+    pub fn ended(&mut self, name: &'static str) {
+        self.ended = Some(name);
+    }
+
+    pub fn start(&mut self, iterations: usize) {
+        self.iterations = iterations;
+    }
+
+    pub fn pending(&mut self, name: &'static str) -> bool {
+        if self.lens.iter().any(|&(seen, _len)| seen == name) {
+            // Repeated interpolation of the same variable.
+            false
+        } else if !self.past_ended && self.ended == Some(name) {
+            self.past_ended = true;
+            self.lens.push((name, RepLen::Exact(self.iterations)));
+            false
+        } else {
+            true
+        }
+    }
+
+    pub fn record(&mut self, name: &'static str, remaining: Option<RepLen>) {
+        if let Some(remaining) = remaining {
+            let taken = self.iterations + !self.past_ended as usize;
+            self.lens.push((name, remaining.offset(taken)));
+        }
+    }
+
+    #[track_caller]
+    pub fn finish(&self) {
+        let first = match self.lens.first() {
+            Some(&(_name, len)) => len,
+            None => return,
+        };
+        if let RepLen::Exact(_) = first {
+            if self.lens.iter().all(|&(_name, len)| len == first) {
+                return;
+            }
+        }
+        let mut msg =
+            String::from("iterators interpolated in the same repetition have different lengths:");
+        for (i, (name, len)) in self.lens.iter().enumerate() {
+            let sep = if i == 0 { " " } else { ", " };
+            let _ = write!(msg, "{}`{}` has {}", sep, name, len);
+        }
+        panic!("{}", msg);
+    }
+}
+
+#[cfg(not(feature = "checked"))]
+impl RepCheck {
+    #[allow(clippy::new_without_default)]
+    #[inline]
+    pub fn new() -> Self {
+        RepCheck
+    }
+
+    #[inline]
+    pub fn ended(&mut self, _name: &'static str) {}
+}
+
+// Index of the current iteration, bound by a repetition whose body begins with
+// `#@name`. Interpolates as an unsuffixed integer, which is what is needed for
+// tuple field access like `self.#i`.
+#[derive(Copy, Clone)]
+#[doc(hidden)]
+pub struct RepIndex(pub usize);
+
+impl Deref for RepIndex {
+    type Target = usize;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl ToTokens for RepIndex {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        tokens.append(Literal::usize_unsuffixed(self.0));
+    }
+}
+
+impl IdentFragment for RepIndex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
 #[doc(hidden)]
 #[inline]
 pub fn get_span<T>(span: T) -> GetSpan<T> {
@@ -277,6 +625,208 @@ pub fn push_group_spanned(
     tokens.append(g);
 }
 
+#[doc(hidden)]
+pub fn push_with<F: FnOnce(&mut TokenStream)>(tokens: &mut TokenStream, f: F) {
+    f(tokens);
+}
+
+// The value of `quote_lazy!`, which quotes its tokens each time it is
+// interpolated.
+#[doc(hidden)]
+pub struct Lazy<F>(pub F);
+
+impl<F: Fn(&mut TokenStream)> ToTokens for Lazy<F> {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        (self.0)(tokens);
+    }
+}
+
+// The text of a doc comment in `#(doc ...)`, in which `#name` refers to one of
+// the variables listed before the comment and `##name` stands for the text
+// `#name`. A `#` right after an identifier character, as in `r#type`, does not
+// start a name.
+#[derive(Copy, Clone)]
+struct DocName {
+    pound: usize,
+    start: usize,
+    end: usize,
+    escaped: bool,
+}
+
+// Fails to compile if the text of a doc comment refers to a name that is not
+// one of the listed variables.
+#[doc(hidden)]
+pub const fn check_doc(text: &str, vars: &[&str]) {
+    let text = text.as_bytes();
+    let mut i = 0;
+    while let Some(found) = next_doc_name(text, i) {
+        if !found.escaped {
+            let mut k = 0;
+            while k < vars.len() && !doc_name_is(text, found, vars[k].as_bytes()) {
+                k += 1;
+            }
+            assert!(
+                k < vars.len(),
+                "a doc comment in `#(doc ...)` refers to a name that is not listed before it; write `##name` for the text `#name`",
+            );
+        }
+        i = found.end;
+    }
+}
+
+const fn doc_name_is(text: &[u8], found: DocName, name: &[u8]) -> bool {
+    if found.end - found.start != name.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < name.len() {
+        if text[found.start + i] != name[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+// The next `#name` or `##name` at or after i.
+const fn next_doc_name(text: &[u8], mut i: usize) -> Option<DocName> {
+    while i < text.len() {
+        if text[i] == b'#' && (i == 0 || !is_ident_byte(text[i - 1])) {
+            let escaped = i + 1 < text.len() && text[i + 1] == b'#';
+            let start = if escaped { i + 2 } else { i + 1 };
+            let mut end = start;
+            while end < text.len()
+                && (is_ident_byte(text[end]) && (end > start || !text[end].is_ascii_digit()))
+            {
+                end += 1;
+            }
+            if end > start {
+                return Some(DocName {
+                    pound: i,
+                    start,
+                    end,
+                    escaped,
+                });
+            }
+        }
+        i += 1;
+    }
+    None
+}
+
+const fn is_ident_byte(b: u8) -> bool {
+    b == b'_' || b.is_ascii_alphanumeric()
+}
+
+// Pushes the attribute of a doc comment whose text has passed check_doc, with
+// each `#name` replaced by the value that lookup finds for it.
+#[doc(hidden)]
+pub fn push_doc(
+    tokens: &mut TokenStream,
+    inner: bool,
+    text: &str,
+    lookup: impl FnMut(&str) -> Option<String>,
+) {
+    push_doc_spanned(tokens, Span::call_site(), inner, text, lookup);
+}
+
+#[doc(hidden)]
+pub fn push_doc_spanned(
+    tokens: &mut TokenStream,
+    span: Span,
+    inner: bool,
+    text: &str,
+    mut lookup: impl FnMut(&str) -> Option<String>,
+) {
+    let mut doc = String::new();
+    let mut i = 0;
+    while let Some(found) = next_doc_name(text.as_bytes(), i) {
+        doc.push_str(&text[i..found.pound]);
+        let name = &text[found.start..found.end];
+        match if found.escaped { None } else { lookup(name) } {
+            Some(value) => doc.push_str(&value),
+            None => {
+                doc.push('#');
+                doc.push_str(name);
+            }
+        }
+        i = found.end;
+    }
+    doc.push_str(&text[i..]);
+
+    push_pound_spanned(tokens, span);
+    if inner {
+        push_bang_spanned(tokens, span);
+    }
+    let mut attr = TokenStream::new();
+    push_ident_spanned(&mut attr, span, "doc");
+    push_eq_spanned(&mut attr, span);
+    let mut literal = Literal::string(&doc);
+    literal.set_span(span);
+    attr.append(literal);
+    push_group_spanned(tokens, span, Delimiter::Bracket, attr);
+}
+
+// Wrapper around a variable that a doc comment refers to, to select by autoref
+// how it is formatted: as by `format_ident!` if it is an identifier, so that a
+// raw identifier loses its `r#`, or else by `Display`, or else as its tokens.
+#[doc(hidden)]
+pub struct DocValue<'a, T: ?Sized>(pub &'a T);
+
+// The `IdentFragment` types whose representation differs from `Display`. Other
+// types are left to `Display`, which unlike `IdentFragment` also covers an
+// integer variable whose type is not yet inferred.
+#[doc(hidden)]
+pub trait DocIdent: IdentFragment {}
+
+impl DocIdent for Ident {}
+
+impl<T: DocIdent + ?Sized> DocIdent for &T {}
+
+impl<T: DocIdent> DocIdent for RepInterp<T> {}
+
+#[doc(hidden)]
+pub mod doc_ext {
+    use super::{DocIdent, DocValue, IdentFragmentAdapter};
+    use crate::ToTokens;
+    use alloc::format;
+    use alloc::string::{String, ToString};
+    use core::fmt::Display;
+
+    #[doc(hidden)]
+    pub trait DocIdentExt {
+        fn quote_doc_value(&self) -> String;
+    }
+
+    impl<T: DocIdent + ?Sized> DocIdentExt for &&DocValue<'_, T> {
+        fn quote_doc_value(&self) -> String {
+            format!("{}", IdentFragmentAdapter(self.0))
+        }
+    }
+
+    #[doc(hidden)]
+    pub trait DocDisplayExt {
+        fn quote_doc_value(&self) -> String;
+    }
+
+    impl<T: Display + ?Sized> DocDisplayExt for &DocValue<'_, T> {
+        fn quote_doc_value(&self) -> String {
+            format!("{}", self.0)
+        }
+    }
+
+    #[doc(hidden)]
+    pub trait DocTokensExt {
+        fn quote_doc_value(&self) -> String;
+    }
+
+    impl<T: ToTokens + ?Sized> DocTokensExt for DocValue<'_, T> {
+        fn quote_doc_value(&self) -> String {
+            self.0.to_token_stream().to_string()
+        }
+    }
+}
+
 #[doc(hidden)]
 pub fn parse(tokens: &mut TokenStream, s: &str) {
     let s: TokenStream = s.parse().expect("invalid token stream");
@@ -451,6 +1001,62 @@ pub fn mk_ident(id: &str, span: Option<Span>) -> Ident {
     ident_maybe_raw(id, span)
 }
 
+#[doc(hidden)]
+pub fn mk_lit(text: &str, span: Option<Span>) -> Literal {
+    let mut literal = Literal::string(text);
+    literal.set_span(span.unwrap_or_else(Span::call_site));
+    literal
+}
+
+// Accumulates the fragments of an `#(ident ...)` in `quote!`, taking the span
+// of the first interpolated fragment that has one, the same as `format_ident!`.
+#[derive(Default)]
+#[doc(hidden)]
+pub struct IdentConcat {
+    id: String,
+    span: Option<Span>,
+}
+
+impl IdentConcat {
+    // A fragment written literally in the macro input. An `r#` prefix is kept
+    // only at the start, where it makes the whole identifier raw.
+    pub fn push_str(&mut self, fragment: &str) {
+        let fragment = match fragment.strip_prefix("r#") {
+            Some(unraw) if !self.id.is_empty() => unraw,
+            _ => fragment,
+        };
+        self.id.push_str(fragment);
+    }
+
+    pub fn push<T: IdentFragment + ?Sized>(&mut self, fragment: &T) {
+        self.span = self.span.or_else(|| fragment.span());
+        let _ = write!(self.id, "{}", IdentFragmentAdapter(fragment));
+    }
+
+    pub fn finish(self, span: Option<Span>) -> Ident {
+        mk_ident(&self.id, self.span.or(span))
+    }
+}
+
+// Whether a literal written inside of `#(ident ...)` can continue an
+// identifier. Integers like `2`, `0x1f` or `8u8` can, while strings, chars and
+// floats would only produce an invalid identifier at runtime.
+#[doc(hidden)]
+pub const fn is_ident_literal(repr: &str) -> bool {
+    let bytes = repr.as_bytes();
+    if bytes.is_empty() || !bytes[0].is_ascii_digit() {
+        return false;
+    }
+    let mut i = 1;
+    while i < bytes.len() {
+        if !bytes[i].is_ascii_alphanumeric() && bytes[i] != b'_' {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
 fn ident_maybe_raw(id: &str, span: Span) -> Ident {
     if let Some(id) = id.strip_prefix("r#") {
         Ident::new_raw(id, span)
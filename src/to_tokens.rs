@@ -4,10 +4,10 @@ use alloc::boxed::Box;
 use alloc::ffi::CString;
 use alloc::rc::Rc;
 use alloc::string::String;
+use alloc::sync::Arc;
 use core::ffi::CStr;
 use core::iter;
 use proc_macro2::{Group, Ident, Literal, Punct, Span, TokenStream, TokenTree};
-use std::sync::Arc;
 
 /// Types that can be interpolated inside a `quote!` invocation.
 pub trait ToTokens {
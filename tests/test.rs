@@ -8,10 +8,13 @@
 
 extern crate proc_macro;
 
-use proc_macro2::{Delimiter, Group, Ident, Span, TokenStream};
-use quote::{format_ident, quote, quote_spanned, TokenStreamExt};
+use proc_macro2::{Delimiter, Group, Ident, Span, TokenStream, TokenTree};
+use quote::{
+    format_ident, quote, quote_into, quote_lazy, quote_lit, quote_mixed, quote_spanned,
+    quote_spanned_into, QuoteRepeat, TokenStreamExt,
+};
 use std::borrow::Cow;
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, LinkedList, VecDeque};
 use std::ffi::{CStr, CString};
 
 struct X;
@@ -65,6 +68,124 @@ fn test_quote_spanned_impl() {
     assert_eq!(expected, tokens.to_string());
 }
 
+#[test]
+fn test_quote_into() {
+    struct Field(Ident);
+
+    impl quote::ToTokens for Field {
+        fn to_tokens(&self, tokens: &mut TokenStream) {
+            let name = &self.0;
+            quote_into!(tokens => #name: i32,);
+            quote_into!(tokens =>);
+        }
+    }
+
+    let name = format_ident!("Point");
+    let fields = [Field(format_ident!("x")), Field(format_ident!("y"))];
+
+    let mut tokens = quote!(#[derive(Debug)]);
+    quote_into!(&mut tokens =>
+        #(doc #name
+            /// The #name type.
+        )
+        struct #name {
+            #(#fields)*
+        }
+    );
+
+    let expected = concat!(
+        "# [derive (Debug)] ",
+        "# [doc = \" The Point type.\"] ",
+        "struct Point { x : i32 , y : i32 , }",
+    );
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_quote_spanned_into() {
+    let span = Span::call_site();
+    let x = X;
+
+    let mut tokens = TokenStream::new();
+    quote_spanned_into!(&mut tokens, span=> #x <#x>);
+    quote_spanned_into!(&mut tokens, span=> (#x) [#x] {#x});
+
+    assert_eq!("X < X > (X) [X] { X }", tokens.to_string());
+}
+
+#[test]
+fn test_quote_lazy() {
+    let name = format_ident!("Point");
+    let fields = vec![format_ident!("x"), format_ident!("y")];
+    let count = &std::cell::Cell::new(0);
+
+    let body = quote_lazy! {
+        #(#fields: #{{ count.set(count.get() + 1); count.get() }},)*
+    };
+    let def = quote_lazy!(struct #name { #body });
+
+    let tokens = quote!(#def #def);
+    let expected = concat!(
+        "struct Point { x : 1i32 , y : 2i32 , } ",
+        "struct Point { x : 3i32 , y : 4i32 , }",
+    );
+    assert_eq!(expected, tokens.to_string());
+
+    let empty = quote_lazy!();
+    assert_eq!("", quote!(#empty).to_string());
+}
+
+#[test]
+fn test_quote_mixed() {
+    let x = X;
+    let xs = [X, X];
+    let tokens = quote_mixed!(let value = #x; [value] {#(#xs),*});
+    assert_eq!("let value = X ; [value] { X , X }", tokens.to_string());
+
+    // Outside of a procedural macro the spans do not carry hygiene, but their
+    // location tells apart the tokens written inside of the invocation from
+    // the interpolated ones.
+    fn spans(tokens: TokenStream, out: &mut Vec<(String, String)>) {
+        for tt in tokens {
+            out.push((tt.to_string(), format!("{:?}", tt.span())));
+            if let TokenTree::Group(group) = tt {
+                spans(group.stream(), out);
+            }
+        }
+    }
+
+    let ty: TokenStream = "u8".parse().unwrap();
+    let ty_span = format!("{:?}", ty.clone().into_iter().next().unwrap().span());
+    let mixed = format!("{:?}", Span::mixed_site());
+    assert_ne!(ty_span, mixed);
+
+    let mut actual = Vec::new();
+    spans(quote_mixed!(let value: #ty = [value];), &mut actual);
+    let expected = [
+        ("let", &mixed),
+        ("value", &mixed),
+        (":", &mixed),
+        ("u8", &ty_span),
+        ("=", &mixed),
+        ("[value]", &mixed),
+        ("value", &mixed),
+        (";", &mixed),
+    ];
+    let expected: Vec<(String, String)> = expected
+        .iter()
+        .map(|(tt, span)| (tt.to_string(), span.to_string()))
+        .collect();
+    assert_eq!(expected, actual);
+}
+
+#[cfg(quote_def_site)]
+#[test]
+fn test_quote_def() {
+    let x = X;
+    let tokens = quote::quote_def!(struct #x(u8););
+    assert_eq!("struct X (u8) ;", tokens.to_string());
+}
+
 #[test]
 fn test_substitution() {
     let x = X;
@@ -369,6 +490,79 @@ fn test_btreeset_repetition() {
     assert_eq!(expected, tokens.to_string());
 }
 
+#[test]
+fn test_collection_repetition() {
+    let deque: VecDeque<u8> = [1, 2].into_iter().collect();
+    let list: LinkedList<u8> = [3, 4].into_iter().collect();
+    let heap: BinaryHeap<u8> = [5].into_iter().collect();
+    let mut map = BTreeMap::new();
+    map.insert("z", quote!(last));
+    map.insert("a", quote!(first));
+
+    let tokens = quote! {
+        #(#deque)* ; #(#list)* ; #(#heap)* ; #(#map),*
+    };
+
+    let expected = "1u8 2u8 ; 3u8 4u8 ; 5u8 ; first , last";
+    assert_eq!(expected, tokens.to_string());
+
+    // An Option is interpolated as its contents in every iteration rather
+    // than repeated over; #(...)? is the repetition that it controls.
+    let bound = Some(quote!(Clone));
+    let tokens = quote!(#(#deque: #bound),*);
+    assert_eq!("1u8 : Clone , 2u8 : Clone", tokens.to_string());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_hash_repetition() {
+    use quote::Sorted;
+    use std::collections::{HashMap, HashSet};
+
+    let mut set = HashSet::new();
+    set.insert(3u8);
+    set.insert(1u8);
+    set.insert(2u8);
+    let mut map = HashMap::new();
+    map.insert("b", quote!(second));
+    map.insert("a", quote!(first));
+
+    let tokens = quote!(#(#set)*);
+    assert_eq!(tokens.into_iter().count(), 3);
+
+    let set = Sorted(&set);
+    let map = Sorted(&map);
+    let tokens = quote! {
+        #(#set)* ; #(#map),*
+    };
+
+    let expected = "1u8 2u8 3u8 ; first , second";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_custom_repetition() {
+    struct Evens(u8);
+
+    impl QuoteRepeat for Evens {
+        type Iter<'q> = std::iter::StepBy<std::ops::Range<u8>>;
+
+        fn quote_repeat(&self) -> Self::Iter<'_> {
+            (0..self.0).step_by(2)
+        }
+    }
+
+    let evens = Evens(7);
+    let names = ["a", "b", "c", "d"];
+    let tokens = quote! {
+        #(#names = #evens;)*
+        #(#evens)*
+    };
+
+    let expected = "\"a\" = 0u8 ; \"b\" = 2u8 ; \"c\" = 4u8 ; \"d\" = 6u8 ; 0u8 2u8 4u8 6u8";
+    assert_eq!(expected, tokens.to_string());
+}
+
 #[test]
 fn test_variable_name_conflict() {
     // The implementation of `#(...),*` uses the variable `_i` but it should be
@@ -466,6 +660,63 @@ fn test_format_ident_strip_raw() {
     assert_eq!(raw_my_id, "r#MyIdstruct");
 }
 
+#[test]
+fn test_quote_lit() {
+    let id = format_ident!("r#struct");
+    let lit0 = quote_lit!("plain");
+    let lit1 = quote_lit!("missing `{}`", id);
+    let lit2 = quote_lit!("{x} of {}: \"{y}\"", 3usize, x = "one", y = id);
+    let lit3 = quote_lit!("{}", "a\nb", span = Span::call_site());
+
+    assert_eq!(lit0.to_string(), "\"plain\"");
+    assert_eq!(lit1.to_string(), "\"missing `struct`\"");
+    assert_eq!(lit2.to_string(), "\"one of 3: \\\"struct\\\"\"");
+    assert_eq!(lit3.to_string(), "\"a\\nb\"");
+}
+
+#[test]
+fn test_inline_ident() {
+    let name = format_ident!("r#type");
+    let fields = [format_ident!("x"), format_ident!("y")];
+    let n = 2usize;
+
+    let tokens = quote! {
+        fn #(ident get_ #name)() {}
+        fn #(ident #name _mut)() {}
+        fn #(ident r#struct)() {}
+        fn #(ident get_ r#struct)() {}
+        #(
+            fn #(ident set_ #fields 1)() {}
+        )*
+        struct #(ident Tuple #{n + 1});
+    };
+
+    let expected = concat!(
+        "fn get_type () { } ",
+        "fn type_mut () { } ",
+        "fn r#struct () { } ",
+        "fn get_struct () { } ",
+        "fn set_x1 () { } ",
+        "fn set_y1 () { } ",
+        "struct Tuple3 ;",
+    );
+    assert_eq!(expected, tokens.to_string());
+
+    let tokens = quote!(#(ident get_ #name));
+    assert_eq!("get_type", tokens.to_string());
+}
+
+#[test]
+fn test_inline_ident_spanned() {
+    let span = Span::call_site();
+    let fields = [format_ident!("x"), format_ident!("y")];
+    let tokens = quote_spanned!(span=> #(self.#(ident get_ #fields)()),*);
+    assert_eq!("self . get_x () , self . get_y ()", tokens.to_string());
+
+    let tokens = quote_spanned!(span=> #(ident Get #{fields.len()}));
+    assert_eq!("Get2", tokens.to_string());
+}
+
 #[test]
 fn test_outer_line_comment() {
     let tokens = quote! {
@@ -573,3 +824,1158 @@ fn test_type_inference_for_span() {
         let _ = quote_spanned!(proc_macro_span.into()=> ...);
     }
 }
+
+#[test]
+fn test_conditional() {
+    let name = format_ident!("Demo");
+
+    for (is_unit, expected) in [(true, "struct Demo ;"), (false, "struct Demo { }")] {
+        let tokens = quote! {
+            #(if is_unit) {
+                struct #name;
+            } #(else) {
+                struct #name {}
+            }
+        };
+        assert_eq!(expected, tokens.to_string());
+    }
+
+    let tokens = quote!(a #(if 1 + 1 == 2) { b } c #(if false) { d });
+    assert_eq!("a b c", tokens.to_string());
+
+    let tokens = quote!(#(if true) { x } #(if true) { y } #(else) { z } #name);
+    assert_eq!("x y Demo", tokens.to_string());
+}
+
+#[test]
+fn test_conditional_before_operator() {
+    let c = true;
+
+    let tokens = quote!(#(if c) { a } #(else) { b } + z);
+    assert_eq!("a + z", tokens.to_string());
+
+    let tokens = quote!(#(if c) { a } #(else) { b } * z);
+    assert_eq!("a * z", tokens.to_string());
+
+    let tokens = quote!(#(if c) { a } * ? z);
+    assert_eq!("a * ? z", tokens.to_string());
+
+    let tokens = quote!(#(if c) { a } ? * z);
+    assert_eq!("a ? * z", tokens.to_string());
+
+    let span = Span::call_site();
+    let tokens = quote_spanned!(span=> #(if c) { a } #(else) { b } + z);
+    assert_eq!("a + z", tokens.to_string());
+}
+
+#[test]
+fn test_conditional_in_repetition() {
+    let fields = vec!["a", "b"];
+    let public = true;
+
+    let tokens = quote! {
+        #(
+            #(if public) { pub } #fields: u8
+        ),*
+    };
+
+    let expected = r#"pub "a" : u8 , pub "b" : u8"#;
+    assert_eq!(expected, tokens.to_string());
+
+    let names = [format_ident!("f"), format_ident!("g")];
+    let flags = vec![true, false];
+
+    let tokens = quote! {
+        #( #(if *#flags) { pub } fn #names(); )*
+    };
+
+    let expected = "pub fn f () ; fn g () ;";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_conditional_spanned() {
+    let span = Span::call_site();
+    let tokens = quote_spanned!(span=> #(if false) { [a] } #(else) { [b] } c);
+    assert_eq!("[b] c", tokens.to_string());
+}
+
+#[test]
+fn test_span_region() {
+    let name = format_ident!("Demo");
+    let ty = format_ident!("T");
+    let fields = [format_ident!("a"), format_ident!("b")];
+
+    let tokens = quote! {
+        impl #name #(span = ty.span()) { where #ty: Sync } {
+            #(
+                #(span = #fields.span()) {
+                    #fields: #(span = Span::mixed_site()) { u8 },
+                }
+            )*
+        }
+    };
+
+    let expected = "impl Demo where T : Sync { a : u8 , b : u8 , }";
+    assert_eq!(expected, tokens.to_string());
+
+    let span = Span::call_site();
+    let tokens = quote_spanned!(span=> [#(span = span) { (x) }] y);
+    assert_eq!("[(x)] y", tokens.to_string());
+}
+
+#[test]
+fn test_span_region_before_operator() {
+    let s = Span::call_site();
+
+    let tokens = quote!(#(span = s) { a } * z);
+    assert_eq!("a * z", tokens.to_string());
+
+    let tokens = quote!(#(span = s) { a } + z);
+    assert_eq!("a + z", tokens.to_string());
+
+    let tokens = quote_spanned!(s=> #(span = s) { a } * ? z);
+    assert_eq!("a * ? z", tokens.to_string());
+
+    let tokens = quote_spanned!(s=> #(span = s) { a } ? * z);
+    assert_eq!("a ? * z", tokens.to_string());
+}
+
+#[test]
+fn test_expression() {
+    struct Field {
+        name: Ident,
+        index: usize,
+    }
+
+    let field = Field {
+        name: format_ident!("x"),
+        index: 1,
+    };
+
+    let tokens = quote!(#{field.name});
+    assert_eq!("x", tokens.to_string());
+
+    let tokens = quote!(let #{field.name} = [#{field.index + 1}, #{field.name.to_string()}];);
+    let expected = r#"let x = [2usize , "x"] ;"#;
+    assert_eq!(expected, tokens.to_string());
+
+    let span = Span::call_site();
+    let tokens = quote_spanned!(span=> #{field.index});
+    assert_eq!("1usize", tokens.to_string());
+}
+
+#[test]
+fn test_expression_in_repetition() {
+    let names = vec!["a", "bc"];
+    let prefix = "p_";
+
+    let tokens = quote! {
+        #(#names = #{names.len()} #{format_ident!("{}{}", prefix, names)}),*
+    };
+
+    let expected = r#""a" = 1usize p_a , "bc" = 2usize p_bc"#;
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_projection_in_repetition() {
+    struct Field {
+        ident: Ident,
+        ty: TokenStream,
+    }
+
+    impl Field {
+        fn getter(&self) -> Ident {
+            format_ident!("get_{}", self.ident)
+        }
+    }
+
+    let fields = vec![
+        Field {
+            ident: format_ident!("a"),
+            ty: quote!(u8),
+        },
+        Field {
+            ident: format_ident!("b"),
+            ty: quote!(Vec<u8>),
+        },
+    ];
+
+    let tokens = quote! {
+        struct S { #(#{#fields.ident}: #{#fields.ty}),* }
+        impl S { #(fn #{#fields.getter()}(&self) -> &#{#fields.ty} { &self.#{#fields.ident} })* }
+    };
+
+    let expected = concat!(
+        "struct S { a : u8 , b : Vec < u8 > } ",
+        "impl S { ",
+        "fn get_a (& self) -> & u8 { & self . a } ",
+        "fn get_b (& self) -> & Vec < u8 > { & self . b } ",
+        "}",
+    );
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_pounded_expression() {
+    let x = 2u8;
+    let list = [1u8, 2, 3];
+
+    let tokens = quote!(#{ #x * (#x + [#x][0]) } #{ list.iter().map(|v| v * #x).sum::<u8>() });
+    assert_eq!("8u8 12u8", tokens.to_string());
+
+    let tokens = quote!(#(#{ *#list + 1 }),*);
+    assert_eq!("2u8 , 3u8 , 4u8", tokens.to_string());
+}
+
+#[test]
+fn test_long_expression() {
+    let x = 1u32;
+
+    let tokens = quote! {
+        #{
+        1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 +
+        1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 +
+        1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 +
+        1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 +
+        1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32 + 1u32
+        }
+    };
+    assert_eq!("80u32", tokens.to_string());
+
+    let tokens = quote! {
+        #{
+        #x + #x + #x + #x + #x + #x + #x + #x + #x + #x + #x + #x + #x + #x + #x + #x +
+        #x + #x + #x + #x + #x + #x + #x + #x + #x + #x + #x + #x + #x + #x + #x + #x +
+        #x + #x + #x + #x + #x + #x + #x + #x + #x + #x + #x + #x + #x + #x + #x + #x +
+        #x + #x + #x + #x + #x + #x + #x + #x + #x + #x + #x + #x + #x + #x + #x + #x +
+        #x + #x + #x + #x + #x + #x + #x + #x + #x + #x + #x + #x + #x + #x + #x + #x
+        }
+    };
+    assert_eq!("80u32", tokens.to_string());
+}
+
+#[test]
+fn test_optional_repetition() {
+    let name = format_ident!("S");
+    let some = Some(quote!(T: Clone));
+    let none: Option<TokenStream> = None;
+
+    let tokens = quote!(struct #name<T> #(where #some)? ;);
+    assert_eq!("struct S < T > where T : Clone ;", tokens.to_string());
+
+    let tokens = quote!(struct #name<T> #(where #none)? ;);
+    assert_eq!("struct S < T > ;", tokens.to_string());
+
+    // Emitted only if every interpolated Option is Some.
+    let tokens = quote!(#(#some #name #some)? #(#some #none)?);
+    assert_eq!("T : Clone S T : Clone", tokens.to_string());
+
+    let by_ref = &some;
+    let span = Span::call_site();
+    let tokens = quote_spanned!(span=> #(<#by_ref>)?);
+    assert_eq!("< T : Clone >", tokens.to_string());
+}
+
+#[test]
+fn test_optional_in_repetition() {
+    let fields = vec!["a", "b", "c"];
+    let defaults = vec![Some(1u8), None, Some(3u8)];
+
+    let tokens = quote! {
+        #(#fields #(= #defaults)?),*
+    };
+
+    let expected = r#""a" = 1u8 , "b" , "c" = 3u8"#;
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_nonempty_repetition() {
+    let bounds = vec![quote!(Clone), quote!(Send)];
+
+    let tokens = quote!(T: #(#bounds)+*);
+    assert_eq!("T : Clone + Send", tokens.to_string());
+
+    let tokens = quote!(where T: #(#bounds)++ 'static);
+    assert_eq!("where T : Clone Send + 'static", tokens.to_string());
+
+    let tokens = quote!(where T: #(#bounds)+ + 'static);
+    assert_eq!("where T : Clone Send + 'static", tokens.to_string());
+
+    let tokens = quote!(T: #(#bounds + 'static),+);
+    assert_eq!("T : Clone + 'static , Send + 'static", tokens.to_string());
+
+    let span = Span::call_site();
+    let tokens = quote_spanned!(span=> #(#bounds)|+);
+    assert_eq!("Clone | Send", tokens.to_string());
+}
+
+#[test]
+fn test_plus_after_repetition() {
+    let terms = vec![quote!(a), quote!(b)];
+
+    let tokens = quote!(0 #(+ #terms)* + 1);
+    assert_eq!("0 + a + b + 1", tokens.to_string());
+
+    let terms = Some(quote!(a));
+    let tokens = quote!(#(#terms)? + 1);
+    assert_eq!("a + 1", tokens.to_string());
+}
+
+#[test]
+#[should_panic(expected = "repetition `#(...)+` requires at least one element")]
+fn test_empty_nonempty_repetition() {
+    let predicates: Vec<TokenStream> = Vec::new();
+    let _ = quote!(where #(#predicates),+);
+}
+
+#[test]
+fn test_repetition_index() {
+    let names = vec![format_ident!("a"), format_ident!("b"), format_ident!("c")];
+
+    let tokens = quote! {
+        #( #@i let #names = self.#i; )*
+    };
+
+    let expected = "let a = self . 0 ; let b = self . 1 ; let c = self . 2 ;";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_repetition_index_format_ident() {
+    let types = vec![quote!(u8), quote!(String)];
+
+    let tokens = quote! {
+        #( #@i #{format_ident!("__field{}", #i)}: #types ),*
+    };
+
+    let expected = "__field0 : u8 , __field1 : String";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_nested_repetition_index() {
+    let outer = vec!["x", "y"];
+    let inner = vec![vec![10u8, 20u8], vec![30u8]];
+
+    let tokens = quote! {
+        #( #@i #outer => [ #( #@j (#i, #j, #inner) ),* ]; )*
+    };
+
+    let expected = "\"x\" => [(0 , 0 , 10u8) , (0 , 1 , 20u8)] ; \"y\" => [(1 , 0 , 30u8)] ;";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_nested_repetition_index_shadowing() {
+    let i = format_ident!("I");
+    let rows = vec![vec![1u8, 2u8], vec![3u8]];
+
+    let tokens = quote! {
+        #( #i [#( #@i #rows #i ),*] )*
+    };
+
+    let expected = "I [1u8 0 , 2u8 1] I [3u8 0]";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_repetition_index_spanned() {
+    let span = Span::call_site();
+    let names = vec![format_ident!("a"), format_ident!("b")];
+
+    let tokens = quote_spanned! {span=>
+        #( #@i #names = #{*#i + 1} ),*
+    };
+
+    let expected = "a = 1usize , b = 2usize";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_repetition_starting_with_at_ident() {
+    let x = vec![quote!(p), quote!(q)];
+
+    let tokens = quote!(#(@rule #x)*);
+    assert_eq!("@ rule p @ rule q", tokens.to_string());
+
+    let tokens = quote_spanned!(Span::call_site()=> #(@rule #x),*);
+    assert_eq!("@ rule p , @ rule q", tokens.to_string());
+}
+
+#[test]
+fn test_bracketed_separator() {
+    let arms = vec![quote!(A), quote!(B), quote!(C)];
+
+    let tokens = quote! {
+        match x { #(#arms)#[=> (),]* => () }
+    };
+
+    let expected = "match x { A => () , B => () , C => () }";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_bracket_group_separator() {
+    let tys = [X, X];
+
+    let tokens = quote!(#(#tys)[0]*);
+    assert_eq!("X [0] X", tokens.to_string());
+
+    let tokens = quote!(#(#tys)[a b]+ #(#tys)[0]?*);
+    assert_eq!("X [a b] X X [0] X [0]", tokens.to_string());
+
+    let tokens = quote!(f(a) #[attr] * z);
+    assert_eq!("f (a) # [attr] * z", tokens.to_string());
+}
+
+#[test]
+fn test_bracketed_separator_with_interpolation() {
+    let items = vec![1u8, 2u8];
+    let sep = quote!(;);
+
+    let tokens = quote! {
+        #(#items)#[#sep ,]*
+    };
+
+    let expected = "1u8 ; , 2u8";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_interpolated_separator() {
+    let bounds = vec![quote!(Clone), quote!(Send)];
+    let patterns = vec![quote!(Some(_)), quote!(None)];
+    let plus = quote!(+);
+    let pipe = quote!(|);
+
+    let tokens = quote! {
+        T: #(#bounds)#plus*;
+        match x { #(#patterns)#pipe+ => {} }
+    };
+
+    let expected = "T : Clone + Send ; match x { Some (_) | None => { } }";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_interpolated_separator_spanned() {
+    let span = Span::call_site();
+    let bounds = vec![quote!(Clone), quote!(Send)];
+    let plus = quote!(+);
+
+    let tokens = quote_spanned! {span=>
+        T: #(#bounds)#plus* + #(#bounds)#[+ 'static +]*
+    };
+
+    let expected = "T : Clone + Send + Clone + 'static + Send";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_trailing_separator() {
+    let fields = vec![format_ident!("a"), format_ident!("b")];
+    let none: Vec<Ident> = Vec::new();
+
+    let tokens = quote! {
+        S { #(#fields: 0),?* ..Default::default() }
+        S { #(#none: 0),?* ..Default::default() }
+        f(#(#fields),*,);
+        #(#fields)#[=> (),]?*
+    };
+
+    let expected = concat!(
+        "S { a : 0 , b : 0 , .. Default :: default () } ",
+        "S { .. Default :: default () } ",
+        "f (a , b ,) ; ",
+        "a => () , b => () ,",
+    );
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_trailing_separator_spanned() {
+    let span = Span::call_site();
+    let params = vec![format_ident!("T"), format_ident!("U")];
+
+    let tokens = quote_spanned! {span=>
+        <#(#params),?*>
+    };
+
+    let expected = "< T , U , >";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_star_star_question() {
+    let x = vec![quote!(a), quote!(b)];
+
+    let tokens = quote!(#(#x)**?);
+
+    let expected = "a b * ?";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_question_after_separated_repetition() {
+    let segs = vec![format_ident!("a"), format_ident!("b")];
+
+    let tokens = quote!(x = #(#segs).*?;);
+    assert_eq!("x = a . b ? ;", tokens.to_string());
+
+    let tokens = quote!(#(#segs)#[.]*?);
+    assert_eq!("a . b ?", tokens.to_string());
+
+    let span = Span::call_site();
+    let tokens = quote_spanned!(span=> #(#segs).*?);
+    assert_eq!("a . b ?", tokens.to_string());
+
+    let k = quote!(k);
+    let tokens = quote!(#(#k)#[.]? x);
+    assert_eq!("# (k) # [.] ? x", tokens.to_string());
+}
+
+#[test]
+fn test_long_forms_without_pound() {
+    let span = Span::call_site();
+    let c = quote!(k);
+
+    let tokens = quote! {
+        (a) , ? * (a) # ? * (b) #c * (b) #c + (for x in y) { z } , *
+    };
+
+    let expected = "(a) , ? * (a) # ? * (b) k * (b) k + (for x in y) { z } , *";
+    assert_eq!(expected, tokens.to_string());
+
+    let tokens = quote_spanned! {span=>
+        (a) , ? * (b) #c * (for x in y) { z } , *
+    };
+
+    let expected = "(a) , ? * (b) k * (for x in y) { z } , *";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_zipped_repetition_same_length() {
+    let a = vec![1u8, 2u8, 3u8];
+    let b = ["x", "y", "z"];
+    let c = quote!(c);
+
+    let tokens = quote!(#(#a #b #c #a)*);
+
+    let expected = "1u8 \"x\" c 1u8 2u8 \"y\" c 2u8 3u8 \"z\" c 3u8";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[cfg(feature = "checked")]
+#[test]
+#[should_panic(
+    expected = "iterators interpolated in the same repetition have different lengths: `a` has length 3, `b` has length 2"
+)]
+fn test_checked_length_mismatch() {
+    let a = vec![1u8, 2u8, 3u8];
+    let b = vec![4u8, 5u8];
+
+    let _ = quote!(#(#a #b)*);
+}
+
+#[cfg(feature = "checked")]
+#[test]
+#[should_panic(
+    expected = "iterators interpolated in the same repetition have different lengths: `a` has length 1, `b` has length 2"
+)]
+fn test_checked_length_mismatch_first_shorter() {
+    let a = vec![1u8];
+    let b = vec![4u8, 5u8];
+
+    let _ = quote!(#(#a #b),*);
+}
+
+#[cfg(feature = "checked")]
+#[test]
+#[should_panic(
+    expected = "iterators interpolated in the same repetition have different lengths: `a` has length 2, `b` has length at least 3"
+)]
+fn test_checked_length_mismatch_unbounded() {
+    let a = vec![1u8, 2u8];
+    let b = 0u8..;
+
+    let _ = quote!(#(#a #b)*);
+}
+
+#[cfg(feature = "checked")]
+#[test]
+#[should_panic(
+    expected = "iterators interpolated in the same repetition have different lengths: `b` has length at least 5, `a` has length 3"
+)]
+fn test_checked_length_mismatch_unbounded_first() {
+    let a = vec![1u8, 2u8, 3u8];
+    let b = std::iter::repeat(7u8);
+
+    let _ = quote!(#(#b #a)*);
+}
+
+#[cfg(feature = "checked")]
+#[test]
+fn test_checked_optional_repetition() {
+    let a = Some(quote!(a));
+    let b: Option<TokenStream> = None;
+
+    let tokens = quote!(#(#a #b)?);
+
+    assert!(tokens.is_empty());
+}
+
+#[test]
+fn test_for_pattern() {
+    let pairs = vec![
+        (format_ident!("a"), quote!(u8)),
+        (format_ident!("b"), quote!(String)),
+    ];
+
+    let tokens = quote! {
+        struct S { #(for (name, ty) in pairs) { #name: #ty },* }
+        fn f() { #(for (name, _) in pairs.iter().rev()) { drop(#name); }* }
+    };
+
+    let expected = concat!(
+        "struct S { a : u8 , b : String } ",
+        "fn f () { drop (b) ; drop (a) ; }",
+    );
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_for_pattern_separators() {
+    let pairs = [("x", 1u8), ("y", 2u8)];
+
+    let tokens = quote! {
+        #(for &(k, v) in &pairs) { #k => #v }#[, ]* ;
+        #(for (i, (k, _)) in pairs.iter().enumerate()) { #k = #i }* * 2
+    };
+
+    let expected = "\"x\" => 1u8 , \"y\" => 2u8 ; \"x\" = 0usize \"y\" = 1usize * 2";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_for_pattern_in_repetition() {
+    let structs = vec![format_ident!("A"), format_ident!("B")];
+    let fields = vec![
+        vec![(format_ident!("x"), quote!(u8))],
+        vec![
+            (format_ident!("y"), quote!(u16)),
+            (format_ident!("z"), quote!(u32)),
+        ],
+    ];
+
+    let tokens = quote! {
+        #(
+            struct #structs {
+                #(for (name, ty) in #fields) { #name: #ty },*
+            }
+        )*
+    };
+
+    let expected = "struct A { x : u8 } struct B { y : u16 , z : u32 }";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_for_pattern_scope_in_repetition() {
+    let x = format_ident!("X");
+    let rows = vec![vec![1u8, 2u8], vec![3u8]];
+
+    let tokens = quote! {
+        #( [#(for x in #rows) { #x },*] #x )*
+    };
+
+    let expected = "[1u8 , 2u8] X [3u8] X";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_for_pattern_spanned() {
+    let span = Span::call_site();
+    let pairs = vec![(format_ident!("a"), 1u8), (format_ident!("b"), 2u8)];
+
+    let tokens = quote_spanned! {span=>
+        #(for (name, value) in pairs) { let #name = #value; }*
+    };
+
+    let expected = "let a = 1u8 ; let b = 2u8 ;";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_higher_ranked_bound_in_repetition() {
+    let tys = [format_ident!("A"), format_ident!("B")];
+
+    let tokens = quote! {
+        where #(for<'a> &'a #tys: Tr<'a>),*
+    };
+
+    let expected = "where for < 'a > & 'a A : Tr < 'a > , for < 'a > & 'a B : Tr < 'a >";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_match() {
+    enum Style {
+        Named(Vec<Ident>),
+        Unnamed(usize),
+        Unit,
+    }
+
+    let name = format_ident!("S");
+    let styles = [
+        Style::Named(vec![format_ident!("a"), format_ident!("b")]),
+        Style::Unnamed(2),
+        Style::Unit,
+    ];
+
+    let mut tokens = TokenStream::new();
+    for style in &styles {
+        tokens.extend(quote! {
+            struct #name #(match style) {
+                Style::Named(fields) => { { #(#fields: u8),* } }
+                Style::Unnamed(n) if *n > 1 => { (u8, ...); }
+                Style::Unnamed(_) => { (u8); },
+                Style::Unit => { ; }
+            }
+        });
+    }
+
+    let expected = concat!(
+        "struct S { a : u8 , b : u8 } ",
+        "struct S (u8 , ...) ; ",
+        "struct S ;",
+    );
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_match_in_repetition() {
+    let names = vec![format_ident!("A"), format_ident!("B"), format_ident!("C")];
+    let fields = vec![Some(format_ident!("x")), None, Some(format_ident!("y"))];
+    let ty = quote!(u8);
+
+    let tokens = quote! {
+        #(
+            struct #names #(match *#fields) {
+                Some(ref field) => { { #field: #ty } }
+                None => { ; }
+            }
+        )*
+    };
+
+    let expected = "struct A { x : u8 } struct B ; struct C { y : u8 }";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_match_arm_scope_in_repetition() {
+    enum Kind {
+        A(Ident),
+        B,
+    }
+
+    let ty = format_ident!("Outer");
+    let kinds = vec![Kind::A(format_ident!("Inner")), Kind::B];
+
+    let tokens = quote! {
+        #(
+            #(match *#kinds) {
+                Kind::A(ty) => { a(#ty) }
+                Kind::B => { b(#ty) }
+            }
+        )*
+    };
+
+    let expected = "a (Inner) b (Outer)";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_match_before_operator() {
+    let v = 1;
+
+    let tokens = quote!(#(match v) { _ => { a } } + z);
+    assert_eq!("a + z", tokens.to_string());
+
+    let tokens = quote!(#(match v) { _ => { a } } * ? z);
+    assert_eq!("a * ? z", tokens.to_string());
+
+    let tokens = quote!(#(match v) { _ => { a } } ? * z);
+    assert_eq!("a ? * z", tokens.to_string());
+
+    let span = Span::call_site();
+    let tokens = quote_spanned!(span=> #(match v) { _ => { a } } * z);
+    assert_eq!("a * z", tokens.to_string());
+}
+
+#[test]
+fn test_match_spanned() {
+    let span = Span::call_site();
+    let value = Some(1u8);
+
+    let tokens = quote_spanned! {span=>
+        #(match value) {
+            Some(v) => { Some(#v) }
+            None => { None }
+        }
+    };
+
+    let expected = "Some (1u8)";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_let() {
+    let name = format_ident!("Demo");
+    let fields = vec![format_ident!("a"), format_ident!("b")];
+
+    let tokens = quote! {
+        #(let builder = format_ident!("{}Builder", name);)
+        struct #builder {
+            #(#fields: Option<u8>,)*
+        }
+        impl #name {
+            fn builder() -> #builder {
+                #(let count: usize = fields.len();)
+                #builder::with_capacity(#count)
+            }
+        }
+    };
+
+    let expected = concat!(
+        "struct DemoBuilder { a : Option < u8 > , b : Option < u8 > , } ",
+        "impl Demo { fn builder () -> DemoBuilder { DemoBuilder :: with_capacity (2usize) } }",
+    );
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_let_in_repetition() {
+    let fields = vec![format_ident!("a"), format_ident!("b")];
+
+    let tokens = quote! {
+        #(
+            #(let setter = format_ident!("set_{}", #fields);)
+            fn #setter(&mut self, value: u8) { self.#fields = value; }
+        )*
+        #(let #fields = 0;)*
+    };
+
+    let expected = concat!(
+        "fn set_a (& mut self , value : u8) { self . a = value ; } ",
+        "fn set_b (& mut self , value : u8) { self . b = value ; } ",
+        "let a = 0 ; let b = 0 ;",
+    );
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_let_scope_in_repetition() {
+    let xs = vec![1u8, 2u8];
+    let ys = vec![3u8, 4u8];
+
+    let tokens = quote! {
+        #( [#(let y = #xs;) #y #ys { #(let z = #y;) #z #ys }] )*
+    };
+
+    let expected = "[1u8 3u8 { 1u8 3u8 }] [2u8 4u8 { 2u8 4u8 }]";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_let_spanned() {
+    let span = Span::call_site();
+    let name = "x";
+
+    let tokens = quote_spanned! {span=>
+        #(let ident = format_ident!("{}", name);)
+        let #ident = #ident;
+    };
+
+    let expected = "let x = x ;";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_escaped_pound() {
+    let name = format_ident!("generated");
+    let values = vec![1u8, 2u8];
+
+    let tokens = quote! {
+        fn #name() -> TokenStream {
+            let value = 1;
+            quote!(#[[value]])
+        }
+        #(#[[x]] #values)*
+    };
+
+    let expected = concat!(
+        "fn generated () -> TokenStream { let value = 1 ; quote ! (# value) } ",
+        "# x 1u8 # x 2u8",
+    );
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_escaped_pound_spanned() {
+    let span = Span::call_site();
+    let ty = quote!(u8);
+
+    let tokens = quote_spanned! {span=>
+        let tokens = quote!(#[[ty]]: #ty);
+    };
+
+    let expected = "let tokens = quote ! (# ty : u8) ;";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_pound_before_interpolation() {
+    let x = X;
+
+    let tokens = quote!(quote!(# #x));
+    assert_eq!("quote ! (# X)", tokens.to_string());
+
+    let tokens = quote!(# #x);
+    assert_eq!("# X", tokens.to_string());
+}
+
+#[test]
+fn test_closure_interpolation() {
+    let fields = ["a", "b", "c"];
+
+    let tokens = quote! {
+        struct S { #(|tokens| {
+            for (i, field) in fields.iter().enumerate() {
+                if i == 2 {
+                    break;
+                }
+                tokens.append(format_ident!("{}", field));
+                tokens.append_all(quote!(: u8,));
+            }
+        }) }
+    };
+
+    let expected = "struct S { a : u8 , b : u8 , }";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_closure_in_repetition() {
+    let names = [format_ident!("a"), format_ident!("b")];
+
+    let tokens = quote! {
+        #(#names = #(|tokens: &mut TokenStream| {
+            tokens.append(Ident::new(&names.to_string().to_uppercase(), Span::call_site()));
+        });)*
+    };
+
+    let expected = "a = A ; b = B ;";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_closure_patterns() {
+    let name = format_ident!("x");
+    let pair = (format_ident!("a"), format_ident!("b"));
+
+    let tokens = quote! {
+        f(#(|_| {})) = #(|&mut ref mut tokens| {
+            tokens.append(Ident::new("y", Span::call_site()));
+        }) #(move |tokens| tokens.append(name)) #(move |&mut ref mut tokens| tokens.append_all([pair.0, pair.1]))
+    };
+
+    let expected = "f () = y x a b";
+    assert_eq!(expected, tokens.to_string());
+
+    let tokens = quote!(#(move |tokens| tokens.append(Ident::new("z", Span::call_site()))));
+    assert_eq!("z", tokens.to_string());
+}
+
+#[test]
+fn test_closure_spanned() {
+    let span = Span::call_site();
+
+    let tokens = quote_spanned! {span=>
+        let x = #(|tokens| tokens.append(Ident::new("y", span)));
+    };
+
+    let expected = "let x = y ;";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_doc_interpolation() {
+    let name = format_ident!("Point");
+    let fields = [format_ident!("x"), format_ident!("y")];
+    let types = [quote!(i32), quote!(u8)];
+    let version = 2;
+
+    let tokens = quote! {
+        #(doc #name #version
+            //! Types for #name, version #version.
+        )
+        #(doc #name
+            /// A #name, not a ##Line.
+            ///
+            /// See [`new`](##method.new).
+        )
+        pub struct #name {
+            #(
+                #(doc #fields #types
+                    /// The #fields field, of type #types.
+                )
+                pub #fields: #types,
+            )*
+        }
+        impl #name {
+            #(doc #name /** Creates a #name. */)
+            pub fn new() -> Self {
+                #name::default() + #version
+            }
+        }
+    };
+
+    let expected = concat!(
+        "# ! [doc = \" Types for Point, version 2.\"] ",
+        "# [doc = \" A Point, not a #Line.\"] ",
+        "# [doc = \"\"] ",
+        "# [doc = \" See [`new`](#method.new).\"] ",
+        "pub struct Point { ",
+        "# [doc = \" The x field, of type i32.\"] pub x : i32 , ",
+        "# [doc = \" The y field, of type u8.\"] pub y : u8 , ",
+        "} ",
+        "impl Point { ",
+        "# [doc = \" Creates a Point. \"] ",
+        "pub fn new () -> Self { Point :: default () + 2i32 } ",
+        "}",
+    );
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_doc_interpolation_escaped() {
+    let name = format_ident!("Point");
+
+    let tokens = quote! {
+        #(doc #name
+            /// ## Usage of ##name in #name, or of r#type and &##x27;
+        )
+        struct #name;
+    };
+
+    let expected =
+        "# [doc = \" ## Usage of #name in Point, or of r#type and &#x27;\"] struct Point ;";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_doc_interpolation_spanned() {
+    let span = Span::call_site();
+    let name = format_ident!("Point");
+
+    let tokens = quote_spanned! {span=>
+        #(doc #name
+            /// Docs for #name.
+        )
+        struct #name;
+    };
+
+    let expected = "# [doc = \" Docs for Point.\"] struct Point ;";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_doc_interpolation_raw_ident() {
+    let field = format_ident!("r#type");
+
+    let tokens = quote! {
+        #(doc #field
+            /// The #field field.
+        )
+        #field: u8,
+    };
+
+    let expected = "# [doc = \" The type field.\"] r#type : u8 ,";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_doc_interpolation_only() {
+    let name = format_ident!("Point");
+
+    let tokens = quote!(#(doc #name /** A #name. */));
+
+    let expected = "# [doc = \" A Point. \"]";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_doc_without_interpolation() {
+    let name = format_ident!("Point");
+
+    let tokens = quote! {
+        /// Docs for #name with #[attr] and # Heading.
+        /// See [`new`](#method.new), the r#type field and &#x27;.
+        struct #name;
+    };
+
+    let expected = concat!(
+        "# [doc = r\" Docs for #name with #[attr] and # Heading.\"] ",
+        "# [doc = r\" See [`new`](#method.new), the r#type field and &#x27;.\"] ",
+        "struct Point ;",
+    );
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_doc_explicit_attribute() {
+    let name = format_ident!("Point");
+
+    let tokens = quote! {
+        #[doc = "Docs for #name."]
+        #(doc #name #[doc = "Interpolated docs for #name."])
+        struct #name;
+    };
+
+    let expected = concat!(
+        "# [doc = \"Docs for #name.\"] ",
+        "# [doc = \"Interpolated docs for Point.\"] ",
+        "struct Point ;",
+    );
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_doc_other_literal() {
+    let name = format_ident!("Point");
+
+    let tokens = quote! {
+        #[doc = 1]
+        #[doc = b"#name"]
+        struct #name;
+    };
+
+    let expected = "# [doc = 1] # [doc = b\"#name\"] struct Point ;";
+    assert_eq!(expected, tokens.to_string());
+}
+
+#[test]
+fn test_doc_repetition() {
+    let fields = [format_ident!("x"), format_ident!("y")];
+
+    let tokens = quote! {
+        #(doc #fields)*
+    };
+
+    let expected = "doc x doc y";
+    assert_eq!(expected, tokens.to_string());
+}
@@ -0,0 +1,5 @@
+use quote::quote;
+
+fn main() {
+    quote!(#(|(tokens): &mut TokenStream| {}) x);
+}
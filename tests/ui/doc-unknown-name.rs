@@ -0,0 +1,11 @@
+use quote::quote;
+
+fn main() {
+    let ty = quote!(u8);
+    quote! {
+        #(doc #ty
+            /// Returns #ty or #other.
+        )
+        fn get() {}
+    };
+}
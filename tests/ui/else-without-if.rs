@@ -0,0 +1,5 @@
+use quote::quote;
+
+fn main() {
+    quote!(a #(else) { b });
+}
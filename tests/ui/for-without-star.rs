@@ -0,0 +1,6 @@
+use quote::quote;
+
+fn main() {
+    let pairs = vec![("a", 1), ("b", 2)];
+    quote!(#(for (k, v) in pairs) { #k: #v });
+}
@@ -0,0 +1,5 @@
+use quote::quote;
+
+fn main() {
+    let _ = quote!(#(ident get_ "a" b));
+}
@@ -0,0 +1,9 @@
+use quote::{format_ident, quote};
+
+fn main() {
+    let y = format_ident!("Outer");
+    let xs = vec![1u8, 2u8];
+    let _ = quote! {
+        #( [#y #(let y = #xs;) #y] )*
+    };
+}
@@ -0,0 +1,6 @@
+use quote::quote;
+
+fn main() {
+    let value = Some(1);
+    quote!(#(match value) { Some(v) => #v, None => {} });
+}
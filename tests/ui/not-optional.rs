@@ -0,0 +1,6 @@
+use quote::quote;
+
+fn main() {
+    let nonopt = vec!['a', 'b'];
+    quote!(#(#nonopt)?);
+}